@@ -4,4 +4,632 @@
 /// - parse [@cite]{.authors} as Author names \cite{cite}
 /// - parse [@cite]{.year}    as \citeyear{cite}
 /// - parse [@cite]{.p}       as \cite{cite}
-use pandoc_ast::{Block, Inline};
+///
+/// In LaTeX, every citation is turned into bibtex commands,
+/// so that the document does not need citeproc:
+///
+/// - [@a; see @b, p. 3] as \cite{a}, see \cite[p. 3]{b}
+/// - @a                 as \nocite{a}\hyperlink{cite.a}{Authors}
+/// - [@a; @b] in text   as Knuth \cite{a} and Hopcroft et al. \cite{b}
+///
+/// The words used to join authors and citations are
+/// read from `lipics.citations` (`and`, `et-al`, `oxford-comma`).
+///
+/// Author names are read from the `.bib` files of the document.
+/// In other formats, the spans only change the citation
+/// mode, and citeproc does the rest.
+use nom::branch::alt;
+use nom::bytes::complete::{take_till, take_till1, take_while1};
+use nom::character::complete::{alpha1, char, multispace0, one_of};
+use nom::combinator::opt;
+use nom::multi::many0;
+use nom::sequence::delimited;
+use nom::IResult;
+use pandoc_ast::{Citation, CitationMode, Inline, MetaValue, MutVisitor};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::utils;
+use crate::utils::braced;
+
+/// What we need to know about a bibliography entry
+/// to render citations without citeproc.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BibEntry {
+    pub key: String,
+    /// Last names of the authors (or editors)
+    pub authors: Vec<String>,
+    pub year: Option<String>,
+}
+
+impl BibEntry {
+    /// Authors in the natbib style
+    /// Knuth / Gray and Reuter / Hopcroft et al.
+    pub fn authors_text(&self, words: &CitationWords) -> Option<String> {
+        match self.authors.as_slice() {
+            [] => None,
+            [a] => Some(a.clone()),
+            [a, b] => Some(format!("{} {} {}", a, words.and, b)),
+            [a, ..] => Some(format!("{} {}", a, words.et_al)),
+        }
+    }
+}
+
+/// The words used when listing authors or citations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CitationWords {
+    pub and: String,
+    pub et_al: String,
+    /// A, B, and C rather than A, B and C
+    pub oxford_comma: bool,
+}
+
+impl Default for CitationWords {
+    fn default() -> Self {
+        CitationWords {
+            and: "and".to_string(),
+            et_al: "et al.".to_string(),
+            oxford_comma: true,
+        }
+    }
+}
+
+/// Reads the citation words from `lipics.citations`
+/// ```yaml
+/// lipics:
+///   citations:
+///     and: und
+///     et-al: u.a.
+///     oxford-comma: false
+/// ```
+pub fn parse_citation_words(meta: &BTreeMap<String, MetaValue>) -> CitationWords {
+    let mut words = CitationWords::default();
+    let get = |key: &str| utils::meta_deep_get(meta, &format!("lipics.citations.{}", key));
+    if let Some(and) = get("and").and_then(|m| utils::meta_to_inline(&m)) {
+        words.and = utils::stringify_inlines(&and);
+    }
+    if let Some(et_al) = get("et-al").and_then(|m| utils::meta_to_inline(&m)) {
+        words.et_al = utils::stringify_inlines(&et_al);
+    }
+    if let Some(oxford) = get("oxford-comma") {
+        match utils::meta_to_bool(&oxford) {
+            Some(b) => words.oxford_comma = b,
+            None => eprintln!("[lipics] lipics.citations.oxford-comma should be true or false"),
+        }
+    }
+    words
+}
+
+/// Parses the beginning of a bibtex entry
+/// @kind{key, -> (kind, key)
+fn bib_entry_key(input: &str) -> IResult<&str, (&str, &str)> {
+    let (input, _) = char('@')(input)?;
+    let (input, kind) = alpha1(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = one_of("{(")(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, key) = take_till1(|c: char| c == ',' || c == '}' || c == ')' || c.is_whitespace())(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = char(',')(input)?;
+    Ok((input, (kind, key)))
+}
+
+/// Parses a field of a bibtex entry
+/// author = {Donald E. Knuth}, -> (author, Donald E. Knuth)
+fn bib_field(input: &str) -> IResult<&str, (&str, &str)> {
+    let (input, _) = multispace0(input)?;
+    let (input, name) = take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '-')(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = char('=')(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, value) = alt((
+        braced,
+        delimited(char('"'), take_till(|c| c == '"'), char('"')),
+        take_while1(|c: char| c.is_alphanumeric()),
+    ))(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = opt(char(','))(input)?;
+    Ok((input, (name, value)))
+}
+
+/// Splits on whitespace outside of braces
+fn split_words(s: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut current = String::new();
+    let mut depth = 0;
+    for c in s.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        if c.is_whitespace() && depth == 0 {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+/// Extracts the last names of a bibtex author list
+/// Jim Gray and Reuter, Andreas -> [Gray, Reuter]
+fn last_names(authors: &str) -> Vec<String> {
+    let words = split_words(authors);
+    words
+        .split(|w| w == "and")
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            let name = name.join(" ");
+            let last = match name.split_once(',') {
+                Some((last, _)) => last.trim().to_string(),
+                None => split_words(&name).pop().unwrap_or_default(),
+            };
+            // {ACM} -> ACM, but keep {\H{o}} in names
+            match last.strip_prefix('{').and_then(|l| l.strip_suffix('}')) {
+                Some(inner) if !inner.contains('{') => inner.to_string(),
+                _ => last,
+            }
+        })
+        .collect()
+}
+
+/// Lists the entries of a bibtex file.
+/// This is not a full bibtex parser: we only look for
+/// the key, the authors and the year of the entries,
+/// skipping @string, @preamble and @comment.
+pub fn parse_bib(content: &str) -> Vec<BibEntry> {
+    let mut entries = vec![];
+    let mut rest = content;
+    while let Some(pos) = rest.find('@') {
+        rest = &rest[pos..];
+        match bib_entry_key(rest) {
+            Ok((next, (kind, key))) => {
+                let kind = kind.to_lowercase();
+                rest = next;
+                if kind == "string" || kind == "preamble" || kind == "comment" {
+                    continue;
+                }
+                let (next, fields) = many0(bib_field)(rest).unwrap_or((rest, vec![]));
+                rest = next;
+                let field = |name: &str| {
+                    fields
+                        .iter()
+                        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                        .map(|(_, v)| v.to_string())
+                };
+                let authors = field("author").or_else(|| field("editor")).map(|a| last_names(&a)).unwrap_or_default();
+                entries.push(BibEntry { key: key.to_string(), authors, year: field("year") });
+            }
+            Err(_) => rest = &rest[1..],
+        }
+    }
+    entries
+}
+
+/// Parses an inline reference of the metadata (CSL YAML)
+/// { id, author: [{family}], issued: ... }
+fn parse_reference(meta: &MetaValue) -> Option<BibEntry> {
+    if let MetaValue::MetaMap(m) = meta {
+        let key = utils::meta_to_string(m.get("id")?)?;
+        let authors = match m.get("author").map(|x| &**x) {
+            Some(MetaValue::MetaList(l)) => l
+                .iter()
+                .filter_map(|a| match a {
+                    MetaValue::MetaMap(a) => a.get("family").and_then(|f| utils::meta_to_inline(f)),
+                    _ => None,
+                })
+                .map(|i| utils::stringify_inlines(&i))
+                .collect(),
+            _ => vec![],
+        };
+        Some(BibEntry { key, authors, year: None })
+    } else {
+        None
+    }
+}
+
+/// Collects the bibliography of the document,
+/// from the `.bib` files listed in `bibliography`
+/// and from the inline `references`.
+pub fn bibliography(meta: &BTreeMap<String, MetaValue>) -> HashMap<String, BibEntry> {
+    let mut entries = HashMap::new();
+
+    let to_path = |m: &MetaValue| utils::meta_to_inline(m).map(|i| utils::stringify_inlines(&i));
+    let files: Vec<String> = match meta.get("bibliography") {
+        Some(MetaValue::MetaList(l)) => l.iter().filter_map(to_path).collect(),
+        Some(m) => to_path(m).into_iter().collect(),
+        None => vec![],
+    };
+    for file in files {
+        if !(file.ends_with(".bib") || file.ends_with(".bibtex")) {
+            continue;
+        }
+        match std::fs::read_to_string(&file) {
+            Ok(content) => {
+                for entry in parse_bib(&content) {
+                    entries.insert(entry.key.clone(), entry);
+                }
+            }
+            Err(e) => eprintln!("[lipics] could not read the bibliography {}: {}", file, e),
+        }
+    }
+
+    if let Some(MetaValue::MetaList(l)) = meta.get("references") {
+        for entry in l.iter().filter_map(parse_reference) {
+            entries.insert(entry.key.clone(), entry);
+        }
+    }
+    entries
+}
+
+/// The natbib-style classes of spans around citations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CiteStyle {
+    /// [@a]{.authors} -> Knuth [1]
+    Authors,
+    /// [@a]{.year} -> 1974
+    Year,
+    /// [@a]{.p} -> [1]
+    Parenthetical,
+}
+
+fn classes_to_cite_style(classes: &[String]) -> Option<CiteStyle> {
+    if classes.contains(&"authors".to_string()) {
+        Some(CiteStyle::Authors)
+    } else if classes.contains(&"year".to_string()) {
+        Some(CiteStyle::Year)
+    } else if classes.contains(&"p".to_string()) {
+        Some(CiteStyle::Parenthetical)
+    } else {
+        None
+    }
+}
+
+/// Removes the separators at the start of a citation suffix
+/// [@a, p. 3] has the suffix ", p. 3"
+pub fn clean_suffix(suffix: &[Inline]) -> Vec<Inline> {
+    let mut suffix = suffix.to_vec();
+    while let Some(first) = suffix.first_mut() {
+        match first {
+            Inline::Space => {
+                suffix.remove(0);
+            }
+            Inline::Str(s) if s.starts_with(',') => {
+                let rest = s.trim_start_matches(',').to_string();
+                if rest.is_empty() {
+                    suffix.remove(0);
+                } else {
+                    *s = rest;
+                }
+            }
+            _ => break,
+        }
+    }
+    suffix
+}
+
+/// Renders citations in LaTeX (bibtex) or
+/// only adjusts citation modes for citeproc.
+#[derive(Debug)]
+pub struct CitationRewriter {
+    bibliography: HashMap<String, BibEntry>,
+    words: CitationWords,
+    latex: bool,
+}
+
+impl CitationRewriter {
+    pub fn new(bibliography: HashMap<String, BibEntry>, words: CitationWords, latex: bool) -> CitationRewriter {
+        CitationRewriter { bibliography, words, latex }
+    }
+
+    fn authors_text(&self, key: &str) -> Option<String> {
+        self.bibliography.get(key).and_then(|e| e.authors_text(&self.words))
+    }
+
+    /// A and B / A, B, and C
+    fn conjunction(&self, items: Vec<Vec<Inline>>) -> Vec<Inline> {
+        let mut out = vec![];
+        let n = items.len();
+        for (i, item) in items.into_iter().enumerate() {
+            let last = i > 0 && i == n - 1;
+            if i > 0 && n > 2 && (!last || self.words.oxford_comma) {
+                out.push(Inline::Str(",".to_string()));
+            }
+            if i > 0 {
+                out.push(Inline::Space);
+            }
+            if last {
+                out.push(Inline::Str(self.words.and.clone()));
+                out.push(Inline::Space);
+            }
+            out.extend(item);
+        }
+        out
+    }
+
+    /// \cite{key} or \cite[suffix]{key}
+    fn cite(&self, key: &str, suffix: &[Inline]) -> Vec<Inline> {
+        let format = pandoc_ast::Format("latex".to_string());
+        let suffix = clean_suffix(suffix);
+        if suffix.is_empty() {
+            vec![Inline::RawInline(format, format!("\\cite{{{}}}", key))]
+        } else {
+            let mut inlines = vec![Inline::RawInline(format.clone(), "\\cite[".to_string())];
+            inlines.extend(suffix);
+            inlines.push(Inline::RawInline(format, format!("]{{{}}}", key)));
+            inlines
+        }
+    }
+
+    /// \nocite{key}\hyperlink{cite.key}{text}
+    fn hyperlink(&self, key: &str, text: &str) -> Inline {
+        Inline::RawInline(
+            pandoc_ast::Format("latex".to_string()),
+            format!("\\nocite{{{}}}\\hyperlink{{cite.{}}}{{{}}}", key, key, text),
+        )
+    }
+
+    /// [see @a; @b, p. 3] -> see \cite{a}, \cite[p. 3]{b}
+    /// [@a; @b]           -> \cite{a,b}
+    fn parenthetical(&self, citations: &[Citation]) -> Vec<Inline> {
+        let format = pandoc_ast::Format("latex".to_string());
+        let plain = citations
+            .iter()
+            .all(|c| c.citationPrefix.is_empty() && c.citationSuffix.is_empty());
+        if plain {
+            let keys: Vec<&str> = citations.iter().map(|c| c.citationId.as_str()).collect();
+            return vec![Inline::RawInline(format, format!("\\cite{{{}}}", keys.join(",")))];
+        }
+        let mut inlines = vec![];
+        for (i, c) in citations.iter().enumerate() {
+            if i > 0 {
+                inlines.push(Inline::Str(",".to_string()));
+                inlines.push(Inline::Space);
+            }
+            if !c.citationPrefix.is_empty() {
+                inlines.extend(c.citationPrefix.clone());
+                inlines.push(Inline::Space);
+            }
+            inlines.extend(self.cite(&c.citationId, &c.citationSuffix));
+        }
+        inlines
+    }
+
+    /// @a       -> \nocite{a}\hyperlink{cite.a}{Knuth}
+    /// [@a; @b] -> Knuth~\cite{a} and Hopcroft et al.~\cite{b}
+    fn author_in_text(&self, citations: &[Citation]) -> Vec<Inline> {
+        if let [c] = citations {
+            if let Some(authors) = self.authors_text(&c.citationId) {
+                let mut inlines = vec![self.hyperlink(&c.citationId, &authors)];
+                if !c.citationSuffix.is_empty() {
+                    inlines.push(Inline::RawInline(pandoc_ast::Format("latex".to_string()), "~".to_string()));
+                    inlines.extend(self.cite(&c.citationId, &c.citationSuffix));
+                }
+                return inlines;
+            }
+        }
+        self.authors(citations)
+    }
+
+    /// [@a]{.authors}     -> Knuth~\cite{a}
+    /// [@a; @b]{.authors} -> Knuth~\cite{a} and Hopcroft et al.~\cite{b}
+    ///
+    /// The names are raw LaTeX, as written in the .bib file.
+    fn authors(&self, citations: &[Citation]) -> Vec<Inline> {
+        let format = pandoc_ast::Format("latex".to_string());
+        let items = citations
+            .iter()
+            .map(|c| {
+                let mut inlines = vec![];
+                if !c.citationPrefix.is_empty() {
+                    inlines.extend(c.citationPrefix.clone());
+                    inlines.push(Inline::Space);
+                }
+                if let Some(authors) = self.authors_text(&c.citationId) {
+                    inlines.push(Inline::RawInline(format.clone(), format!("{}~", authors)));
+                }
+                inlines.extend(self.cite(&c.citationId, &c.citationSuffix));
+                inlines
+            })
+            .collect();
+        self.conjunction(items)
+    }
+
+    /// [@a]{.year} -> \nocite{a}\hyperlink{cite.a}{1974}
+    /// or \citeyear{a} when the year is unknown
+    fn year(&self, citations: &[Citation]) -> Vec<Inline> {
+        let format = pandoc_ast::Format("latex".to_string());
+        let mut inlines = vec![];
+        for (i, c) in citations.iter().enumerate() {
+            if i > 0 {
+                inlines.push(Inline::Str(",".to_string()));
+                inlines.push(Inline::Space);
+            }
+            match self.bibliography.get(&c.citationId).and_then(|e| e.year.clone()) {
+                Some(year) => inlines.push(self.hyperlink(&c.citationId, &year)),
+                None => inlines.push(Inline::RawInline(format.clone(), format!("\\citeyear{{{}}}", c.citationId))),
+            }
+        }
+        inlines
+    }
+
+    fn cite_to_latex(&self, citations: &[Citation], style: Option<CiteStyle>) -> Vec<Inline> {
+        match style {
+            Some(CiteStyle::Authors) => self.authors(citations),
+            Some(CiteStyle::Year) => self.year(citations),
+            Some(CiteStyle::Parenthetical) => self.parenthetical(citations),
+            None => match citations.first().map(|c| c.citationMode) {
+                Some(CitationMode::AuthorInText) => self.author_in_text(citations),
+                _ => self.parenthetical(citations),
+            },
+        }
+    }
+
+    /// Outside of LaTeX, the style of the span
+    /// becomes the mode of the citations.
+    fn cite_to_pandoc(&self, mut citations: Vec<Citation>, content: Vec<Inline>, style: Option<CiteStyle>) -> Inline {
+        let mode = match style {
+            Some(CiteStyle::Authors) => Some(CitationMode::AuthorInText),
+            Some(CiteStyle::Year) => Some(CitationMode::SuppressAuthor),
+            Some(CiteStyle::Parenthetical) => Some(CitationMode::NormalCitation),
+            None => None,
+        };
+        if let Some(mode) = mode {
+            for c in citations.iter_mut() {
+                c.citationMode = mode;
+            }
+        }
+        Inline::Cite(citations, content)
+    }
+
+    fn rewrite(&self, inline: Inline, style: Option<CiteStyle>) -> Vec<Inline> {
+        match inline {
+            Inline::Cite(citations, _) if self.latex => self.cite_to_latex(&citations, style),
+            Inline::Cite(citations, content) => vec![self.cite_to_pandoc(citations, content, style)],
+            _ => vec![inline],
+        }
+    }
+}
+
+impl MutVisitor for CitationRewriter {
+    fn visit_vec_inline(&mut self, inlines: &mut Vec<Inline>) {
+        let mut new_inlines = vec![];
+        for inline in inlines.drain(..) {
+            match inline {
+                Inline::Span((_, classes, _), content)
+                    if classes_to_cite_style(&classes).is_some()
+                        && content.iter().any(|i| matches!(i, Inline::Cite(_, _))) =>
+                {
+                    let style = classes_to_cite_style(&classes);
+                    for i in content {
+                        new_inlines.extend(self.rewrite(i, style));
+                    }
+                }
+                Inline::Cite(_, _) => new_inlines.extend(self.rewrite(inline, None)),
+                _ => new_inlines.push(inline),
+            }
+        }
+        *inlines = new_inlines;
+        self.walk_vec_inline(inlines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, authors: &[&str], year: Option<&str>) -> BibEntry {
+        BibEntry {
+            key: key.to_string(),
+            authors: authors.iter().map(|a| a.to_string()).collect(),
+            year: year.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn keys_authors_and_years() {
+        let bib = r#"
+@string{acm = "ACM"}
+@Book{knuth74,
+  author = {Donald E. Knuth},
+  title  = {The Art of Computer Programming},
+  year   = 1974,
+}
+@article( gray:93 ,
+  author = "Jim Gray and Reuter, Andreas",
+  year = {1993}
+)
+@comment{ignored, author = {Nobody}}
+@inproceedings{acm23, editor = {{ACM}}}
+"#;
+        assert_eq!(parse_bib(bib), vec![
+            entry("knuth74", &["Knuth"], Some("1974")),
+            entry("gray:93", &["Gray", "Reuter"], Some("1993")),
+            entry("acm23", &["ACM"], None),
+        ]);
+    }
+
+    #[test]
+    fn braced_last_names() {
+        assert_eq!(last_names("Paul Erd{\\H{o}}s and {van der Waals}, J."), vec!["Erd{\\H{o}}s", "van der Waals"]);
+    }
+
+    #[test]
+    fn authors_text() {
+        let words = CitationWords::default();
+        assert_eq!(entry("a", &[], None).authors_text(&words), None);
+        assert_eq!(entry("a", &["Gray", "Reuter"], None).authors_text(&words), Some("Gray and Reuter".to_string()));
+        assert_eq!(entry("a", &["Aho", "Sethi", "Ullman"], None).authors_text(&words), Some("Aho et al.".to_string()));
+    }
+
+    fn citation(id: &str) -> Citation {
+        Citation {
+            citationId: id.to_string(),
+            citationPrefix: vec![],
+            citationSuffix: vec![],
+            citationMode: CitationMode::NormalCitation,
+            citationNoteNum: 0,
+            citationHash: 0,
+        }
+    }
+
+    fn latex(s: &str) -> Inline {
+        Inline::RawInline(pandoc_ast::Format("latex".to_string()), s.to_string())
+    }
+
+    #[test]
+    fn raw_author_names() {
+        let bibliography = HashMap::from([("erdos".to_string(), entry("erdos", &["Erd{\\H{o}}s"], None))]);
+        let rewriter = CitationRewriter::new(bibliography, CitationWords::default(), true);
+        assert_eq!(rewriter.authors(&[citation("erdos")]), vec![latex("Erd{\\H{o}}s~"), latex("\\cite{erdos}")]);
+        assert_eq!(rewriter.authors(&[citation("unknown")]), vec![latex("\\cite{unknown}")]);
+    }
+
+    fn author_in_text(oxford_comma: bool, keys: &[&str]) -> String {
+        let bibliography = HashMap::from([
+            ("knuth".to_string(), entry("knuth", &["Knuth"], Some("1974"))),
+            ("gray".to_string(), entry("gray", &["Gray", "Reuter"], Some("1993"))),
+            ("aho".to_string(), entry("aho", &["Aho", "Sethi", "Ullman"], Some("1986"))),
+        ]);
+        let words = CitationWords { oxford_comma, ..CitationWords::default() };
+        let rewriter = CitationRewriter::new(bibliography, words, true);
+        let citations: Vec<Citation> = keys.iter().map(|k| Citation { citationMode: CitationMode::AuthorInText, ..citation(k) }).collect();
+        utils::stringify_inlines(&rewriter.cite_to_latex(&citations, None))
+    }
+
+    #[test]
+    fn author_in_text_joins() {
+        assert_eq!(author_in_text(true, &["knuth"]), "\\nocite{knuth}\\hyperlink{cite.knuth}{Knuth}");
+        assert_eq!(author_in_text(true, &["knuth", "gray"]), "Knuth~\\cite{knuth} and Gray and Reuter~\\cite{gray}");
+        assert_eq!(author_in_text(false, &["knuth", "gray"]), "Knuth~\\cite{knuth} and Gray and Reuter~\\cite{gray}");
+        assert_eq!(author_in_text(true, &["knuth", "gray", "aho"]),
+                   "Knuth~\\cite{knuth}, Gray and Reuter~\\cite{gray}, and Aho et al.~\\cite{aho}");
+        assert_eq!(author_in_text(false, &["knuth", "gray", "aho"]),
+                   "Knuth~\\cite{knuth}, Gray and Reuter~\\cite{gray} and Aho et al.~\\cite{aho}");
+    }
+
+    #[test]
+    fn author_in_text_suffix() {
+        let bibliography = HashMap::from([("knuth".to_string(), entry("knuth", &["Knuth"], None))]);
+        let rewriter = CitationRewriter::new(bibliography, CitationWords::default(), true);
+        let c = Citation {
+            citationMode: CitationMode::AuthorInText,
+            citationSuffix: vec![Inline::Str("p.".to_string()), Inline::Space, Inline::Str("3".to_string())],
+            ..citation("knuth")
+        };
+        assert_eq!(rewriter.cite_to_latex(&[c], None), vec![
+            latex("\\nocite{knuth}\\hyperlink{cite.knuth}{Knuth}"),
+            latex("~"),
+            latex("\\cite["),
+            Inline::Str("p.".to_string()),
+            Inline::Space,
+            Inline::Str("3".to_string()),
+            latex("]{knuth}"),
+        ]);
+    }
+}
@@ -0,0 +1,187 @@
+/// Inflection rules for knowledges.
+///
+/// A reference like [automata]{.ref} should resolve to
+/// the knowledge `automaton` without writing `kl=automaton`.
+/// An inflection rule rewrites the end of the text of a
+/// reference (automata -> automaton) before looking it up
+/// again in the knowledge base.
+///
+/// The rules are read from `lipics.knowledge.inflections`:
+///
+/// ```yaml
+/// lipics:
+///   knowledge:
+///     inflections:
+///       - { suffix: "en", replacement: "", name: "german plural" }
+///       - english
+/// ```
+///
+/// where `english` stands for the default rules.
+/// Without this key, the default English rules are used,
+/// and `inflections: false` disables inflections.
+use pandoc_ast::MetaValue;
+use std::collections::BTreeMap;
+
+use crate::utils;
+
+/// Replaces `suffix` by `replacement`
+/// at the end of a reference
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InflectionRule {
+    pub name: String,
+    pub suffix: String,
+    pub replacement: String,
+}
+
+impl InflectionRule {
+    pub fn new(name: &str, suffix: &str, replacement: &str) -> InflectionRule {
+        InflectionRule {
+            name: name.to_string(),
+            suffix: suffix.to_string(),
+            replacement: replacement.to_string(),
+        }
+    }
+
+    /// The text with the rule applied, if the rule applies
+    pub fn apply(&self, text: &str) -> Option<String> {
+        let stem = text.strip_suffix(&self.suffix)?;
+        if stem.is_empty() {
+            return None;
+        }
+        Some(format!("{}{}", stem, self.replacement))
+    }
+}
+
+/// Common English plural and possessive forms,
+/// the most specific ones first. Possessives are given
+/// with both the straight and the typographic apostrophe,
+/// since pandoc's `smart` extension turns ' into ’.
+pub fn english_rules() -> Vec<InflectionRule> {
+    vec![
+        InflectionRule::new("possessive 's", "'s", ""),
+        InflectionRule::new("possessive ’s", "’s", ""),
+        InflectionRule::new("plural possessive s'", "s'", ""),
+        InflectionRule::new("plural possessive s’", "s’", ""),
+        InflectionRule::new("possessive s' (ending in s)", "s'", "s"),
+        InflectionRule::new("possessive s’ (ending in s)", "s’", "s"),
+        InflectionRule::new("plural -ata (automaton)", "ata", "aton"),
+        InflectionRule::new("plural -ena (phenomenon)", "ena", "enon"),
+        InflectionRule::new("plural -ices (index)", "ices", "ex"),
+        InflectionRule::new("plural -ices (matrix)", "ices", "ix"),
+        InflectionRule::new("plural -ies (property)", "ies", "y"),
+        InflectionRule::new("plural -ves (leaf)", "ves", "f"),
+        InflectionRule::new("plural -ves (knife)", "ves", "fe"),
+        InflectionRule::new("plural -es (class)", "es", ""),
+        InflectionRule::new("plural -s (word)", "s", ""),
+        InflectionRule::new("plural -i (radius)", "i", "us"),
+        InflectionRule::new("plural -a (datum)", "a", "um"),
+    ]
+}
+
+fn parse_inflection_rule(meta: &MetaValue) -> Option<Vec<InflectionRule>> {
+    match meta {
+        MetaValue::MetaMap(m) => {
+            let text = |key: &str| {
+                m.get(key)
+                    .and_then(|v| utils::meta_to_inline(v))
+                    .map(|i| utils::stringify_inlines(&i))
+            };
+            let suffix = text("suffix")?;
+            let replacement = text("replacement").unwrap_or_default();
+            let name = text("name").unwrap_or_else(|| format!("-{} -> -{}", suffix, replacement));
+            Some(vec![InflectionRule { name, suffix, replacement }])
+        }
+        _ => match utils::meta_to_string(meta)?.as_str() {
+            "english" => Some(english_rules()),
+            _ => None,
+        },
+    }
+}
+
+/// Reads the inflection rules from `lipics.knowledge.inflections`
+pub fn parse_inflection_rules(meta: &BTreeMap<String, MetaValue>) -> Vec<InflectionRule> {
+    match utils::meta_deep_get(meta, "lipics.knowledge.inflections") {
+        None => english_rules(),
+        Some(MetaValue::MetaList(l)) => l
+            .iter()
+            .flat_map(|r| {
+                let rules = parse_inflection_rule(r);
+                if rules.is_none() {
+                    eprintln!("[lipics] invalid inflection rule {:?}", r);
+                }
+                rules.unwrap_or_default()
+            })
+            .collect(),
+        Some(m) => match utils::meta_to_bool(&m) {
+            Some(true) => english_rules(),
+            Some(false) => vec![],
+            None => {
+                eprintln!("[lipics] lipics.knowledge.inflections should be a list of rules");
+                english_rules()
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The names of the rules that turn `text` into `expected`
+    fn rules_for(text: &str, expected: &str) -> Vec<String> {
+        english_rules()
+            .into_iter()
+            .filter(|r| r.apply(text).as_deref() == Some(expected))
+            .map(|r| r.name)
+            .collect()
+    }
+
+    fn rules(yaml: &str) -> Vec<InflectionRule> {
+        let value: serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+        match utils::yaml_to_meta(&value) {
+            Some(MetaValue::MetaMap(m)) => parse_inflection_rules(&m.into_iter().map(|(k, v)| (k, *v)).collect()),
+            _ => parse_inflection_rules(&BTreeMap::new()),
+        }
+    }
+
+    #[test]
+    fn plurals() {
+        assert_eq!(rules_for("automata", "automaton"), vec!["plural -ata (automaton)"]);
+        assert_eq!(rules_for("matrices", "matrix"), vec!["plural -ices (matrix)"]);
+        assert_eq!(rules_for("leaves", "leaf"), vec!["plural -ves (leaf)"]);
+        assert_eq!(rules_for("words", "word"), vec!["plural -s (word)"]);
+    }
+
+    #[test]
+    fn possessives_with_smart_quotes() {
+        assert_eq!(rules_for("automaton's", "automaton"), vec!["possessive 's"]);
+        assert_eq!(rules_for("automaton’s", "automaton"), vec!["possessive ’s"]);
+        assert_eq!(rules_for("graphs’", "graph"), vec!["plural possessive s’"]);
+        assert_eq!(rules_for("Jones’", "Jones"), vec!["possessive s’ (ending in s)"]);
+    }
+
+    #[test]
+    fn rule_names_are_unique() {
+        let rules = english_rules();
+        let mut names: Vec<&str> = rules.iter().map(|r| r.name.as_str()).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), rules.len());
+    }
+
+    #[test]
+    fn empty_stem() {
+        assert_eq!(InflectionRule::new("plural -s", "s", "").apply("s"), None);
+    }
+
+    #[test]
+    fn configured_rules() {
+        assert_eq!(rules("{}"), english_rules());
+        assert_eq!(rules("lipics: {knowledge: {inflections: false}}"), vec![]);
+        let german = rules("lipics: {knowledge: {inflections: [{suffix: en, name: german plural}, english]}}");
+        assert_eq!(german[0], InflectionRule::new("german plural", "en", ""));
+        assert_eq!(german.len(), english_rules().len() + 1);
+        let unnamed = rules("lipics: {knowledge: {inflections: [{suffix: ies, replacement: y}]}}");
+        assert_eq!(unnamed, vec![InflectionRule::new("-ies -> -y", "ies", "y")]);
+    }
+}
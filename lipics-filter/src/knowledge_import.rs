@@ -0,0 +1,340 @@
+/// Importing knowledges from LaTeX.
+///
+/// Converts the declarations of the knowledge package
+///
+/// ```latex
+/// \knowledge{notion, url={https://en.wikipedia.org/wiki/Automaton}}
+///  | automaton
+///  | automata
+///  | run@automata
+/// ```
+///
+/// into entries following knowledge.schema.json,
+/// that can be listed in `knowledge-files`.
+/// The synonyms may also follow the options on the same
+/// line, as in `\knowledge{notion | automaton | automata}`,
+/// and the options may span several lines.
+///
+/// Lines that are not knowledge declarations
+/// (e.g. `\knowledgestyle`) are reported with their
+/// line number, since they cannot be imported.
+use nom::bytes::complete::tag;
+use nom::character::complete::multispace0;
+use nom::IResult;
+use serde_yaml::{Mapping, Value};
+use std::fmt;
+
+use crate::utils::braced;
+
+/// A line of the LaTeX file that could not be imported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportError {
+    /// The line number, starting at 1
+    pub line: usize,
+    pub text: String,
+    pub reason: String,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {} `{}`", self.line, self.reason, self.text)
+    }
+}
+
+/// Parses the start of a knowledge declaration
+/// \knowledge{notion, url=...} | a -> (| a, notion, url=...)
+fn knowledge_declaration(input: &str) -> IResult<&str, &str> {
+    let (input, _) = tag("\\knowledge")(input)?;
+    let (input, _) = multispace0(input)?;
+    braced(input)
+}
+
+/// Splits `input` on `sep`, ignoring the separators
+/// that are between braces
+fn split_top_level(input: &str, sep: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            c if c == sep && depth == 0 => {
+                parts.push(&input[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+/// Removes the outer braces of an option value
+/// {https://...} -> https://...
+fn unbrace(value: &str) -> &str {
+    match braced(value) {
+        Ok(("", inner)) => inner,
+        _ => value,
+    }
+}
+
+/// Undoes the escaping of `utils::escape_latex`
+/// and of the separators of the knowledge package
+fn unescape_latex(s: &str) -> String {
+    s.replace("{|}", "|")
+        .replace("{@}", "@")
+        .replace("\\textbackslash{}", "\\")
+        .replace("\\textasciitilde{}", "~")
+        .replace("\\textasciicircum{}", "^")
+        .replace("\\_", "_")
+        .replace("\\%", "%")
+        .replace("\\#", "#")
+        .replace("\\&", "&")
+        .replace("\\{", "{")
+        .replace("\\}", "}")
+}
+
+/// Removes a LaTeX comment from a line
+fn strip_comment(line: &str) -> &str {
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '%' if !escaped => return &line[..i],
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    line
+}
+
+/// A knowledge being read, with the line of its declaration
+struct PendingEntry {
+    line: usize,
+    text: String,
+    entry: Mapping,
+    synonyms: Vec<Value>,
+    display: Vec<Value>,
+}
+
+impl PendingEntry {
+    /// Reads the options of the declaration,
+    /// returning the ones that have no equivalent
+    fn new(line: usize, text: &str, options: &str) -> (PendingEntry, Vec<String>) {
+        let mut entry = Mapping::new();
+        let mut display = vec![];
+        let mut unsupported = vec![];
+        let mut notion = false;
+        for option in split_top_level(options, ',') {
+            let option = option.trim();
+            let (key, value) = match option.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(unbrace(value.trim()))),
+                None => (option, None),
+            };
+            let field = match (key, value) {
+                ("", None) => continue,
+                ("notion", None) => {
+                    notion = true;
+                    continue;
+                }
+                ("boldface", None) => {
+                    display.push(Value::from("bold"));
+                    continue;
+                }
+                ("italic" | "md" | "smallcaps" | "lowercase" | "uppercase", None) => {
+                    display.push(Value::from(key));
+                    continue;
+                }
+                ("url", Some(_)) => "url",
+                ("color", Some(_)) => "color",
+                ("index", Some(_)) => "index",
+                ("text", Some(_)) => "placeholder-text",
+                _ => {
+                    unsupported.push(option.to_string());
+                    continue;
+                }
+            };
+            let value = value.unwrap_or_default();
+            let value = match field {
+                "url" => value.replace("\\%", "%").replace("\\#", "#"),
+                "index" => unescape_latex(value).replace("\"\"", "\""),
+                _ => unescape_latex(value),
+            };
+            entry.insert(Value::from(field), Value::from(value));
+        }
+        // notions are the default of knowledge.schema.json
+        if !notion {
+            entry.insert(Value::from("notion"), Value::from(false));
+        }
+        let pending = PendingEntry { line, text: text.to_string(), entry, synonyms: vec![], display };
+        (pending, unsupported)
+    }
+
+    /// Adds the synonyms of `| a | b@scope`
+    fn add_synonyms(&mut self, input: &str) {
+        for synonym in split_top_level(input, '|') {
+            let synonym = synonym.trim();
+            if synonym.is_empty() {
+                continue;
+            }
+            let parts = split_top_level(synonym, '@');
+            let value = match parts.as_slice() {
+                [name, scope] => {
+                    let mut scoped = Mapping::new();
+                    scoped.insert(Value::from("name"), Value::from(unescape_latex(name.trim())));
+                    scoped.insert(Value::from("scope"), Value::from(unescape_latex(scope.trim())));
+                    Value::Mapping(scoped)
+                }
+                _ => Value::from(unescape_latex(synonym)),
+            };
+            self.synonyms.push(value);
+        }
+    }
+
+    fn finish(mut self, entries: &mut Vec<Value>, errors: &mut Vec<ImportError>) {
+        if self.synonyms.is_empty() {
+            errors.push(ImportError { line: self.line, text: self.text, reason: "knowledge without synonyms".to_string() });
+            return;
+        }
+        if !self.display.is_empty() {
+            self.entry.insert(Value::from("display"), Value::Sequence(self.display));
+        }
+        self.entry.insert(Value::from("synonyms"), Value::Sequence(self.synonyms));
+        entries.push(Value::Mapping(self.entry));
+    }
+}
+
+/// Whether the braces of `input` are all closed
+fn balanced(input: &str) -> bool {
+    let mut depth = 0;
+    let mut escaped = false;
+    for c in input.chars() {
+        match c {
+            '{' if !escaped => depth += 1,
+            '}' if !escaped => depth -= 1,
+            _ => {}
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    depth <= 0
+}
+
+/// Imports the knowledge declarations of a LaTeX file,
+/// with the lines that could not be imported.
+/// The options of a declaration may span several lines,
+/// until its braces are closed.
+pub fn import_knowledges(input: &str) -> (Vec<Value>, Vec<ImportError>) {
+    let mut entries = vec![];
+    let mut errors = vec![];
+    let mut current: Option<PendingEntry> = None;
+    let mut lines = input.lines().enumerate();
+    while let Some((i, raw)) = lines.next() {
+        let mut line = strip_comment(raw).trim().to_string();
+        let mut text = raw.trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with("\\knowledge") {
+            while !balanced(&line) {
+                match lines.next() {
+                    Some((_, next)) => {
+                        line.push(' ');
+                        line.push_str(strip_comment(next).trim());
+                        text.push(' ');
+                        text.push_str(next.trim());
+                    }
+                    None => break,
+                }
+            }
+        }
+        let error = |reason: &str| ImportError { line: i + 1, text: text.clone(), reason: reason.to_string() };
+        if let Ok((rest, options)) = knowledge_declaration(&line) {
+            if let Some(entry) = current.take() {
+                entry.finish(&mut entries, &mut errors);
+            }
+            let mut parts = split_top_level(options, '|').into_iter();
+            let (mut entry, unsupported) = PendingEntry::new(i + 1, &text, parts.next().unwrap_or_default());
+            for option in unsupported {
+                errors.push(error(&format!("unsupported option `{}` in", option)));
+            }
+            for synonyms in parts {
+                entry.add_synonyms(synonyms);
+            }
+            match rest.trim() {
+                "" => {}
+                rest => match rest.strip_prefix('|') {
+                    Some(synonyms) => entry.add_synonyms(synonyms),
+                    None => errors.push(error("cannot import the end of")),
+                },
+            }
+            current = Some(entry);
+        } else if let (Some(entry), Some(synonyms)) = (current.as_mut(), line.strip_prefix('|')) {
+            entry.add_synonyms(synonyms);
+        } else {
+            if let Some(entry) = current.take() {
+                entry.finish(&mut entries, &mut errors);
+            }
+            errors.push(error("cannot import"));
+        }
+    }
+    if let Some(entry) = current.take() {
+        entry.finish(&mut entries, &mut errors);
+    }
+    (entries, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Imports `input`, which must not contain errors
+    fn import(input: &str) -> Value {
+        let (entries, errors) = import_knowledges(input);
+        assert_eq!(errors, vec![]);
+        Value::Sequence(entries)
+    }
+
+    fn yaml(s: &str) -> Value {
+        serde_yaml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn synonyms_on_following_lines() {
+        let input = "\\knowledge{notion}\n | automaton\n | automata\n | run@automata";
+        assert_eq!(import(input), yaml("- synonyms: [automaton, automata, {name: run, scope: automata}]"));
+    }
+
+    #[test]
+    fn synonyms_on_the_same_line() {
+        let input = "\\knowledge{boldface | word | words}";
+        assert_eq!(import(input), yaml("- {notion: false, display: [bold], synonyms: [word, words]}"));
+    }
+
+    #[test]
+    fn multi_line_options() {
+        let input = "\\knowledge{notion,\n  url={https://en.wikipedia.org/wiki/Automaton}, % a comment\n  italic}\n | automaton";
+        assert_eq!(import(input), yaml("- {url: 'https://en.wikipedia.org/wiki/Automaton', display: [italic], synonyms: [automaton]}"));
+    }
+
+    #[test]
+    fn unclosed_options() {
+        let (entries, errors) = import_knowledges("\\knowledge{notion,\n | automaton");
+        assert!(entries.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 1);
+    }
+
+    #[test]
+    fn escaped_values() {
+        let input = "\\knowledge{notion, index={a\\_b!c}, url={https://x.org/a\\%20b}}\n | a{|}b";
+        assert_eq!(import(input), yaml("- {index: 'a_b!c', url: 'https://x.org/a%20b', synonyms: ['a|b']}"));
+    }
+
+    #[test]
+    fn unsupported_lines() {
+        let (entries, errors) = import_knowledges("\\knowledgestyle{notion}{color=red}\n\\knowledge{notion, foo=bar}\n | a");
+        assert_eq!(entries.len(), 1);
+        let lines: Vec<(usize, &str)> = errors.iter().map(|e| (e.line, e.reason.as_str())).collect();
+        assert_eq!(lines, vec![(1, "cannot import"), (2, "unsupported option `foo=bar` in")]);
+    }
+}
@@ -1,27 +1,43 @@
 /// Handle knowledges like the knowledge package in LaTeX.
 /// Better Knowledges.
 /// 
-/// - convert knowledge file to yaml
+/// - convert knowledge file to yaml (`lipics-filter import-knowledges`)
 /// - parse [knowledge]{.ref scope=xxx kl=yyy} as \kl[xxx](yyy){knowledge}
 /// - parse [knowledge]{.intro} as \intro{knowledge}
+/// - load knowledges from the files listed in `knowledge-files`
+/// - link the unmarked synonyms when `lipics.knowledge.autolink` is set
+/// - fill the `::: {#knowledge-index}` (or `.knowledge-index`) div with a glossary
+/// - link the introductions back to their references
+/// - preview the introduction when hovering a reference (HTML)
+/// - parse $\kl{\mathsf{Reg}}$ as a reference inside of a formula
+/// - resolve the commands of a `kl-scope=xxx` div or section
+///   in the scope xxx first, then globally
+/// - declare the knowledges for the knowledge package (LaTeX)
+/// - report the notions that are never introduced, even unused ones
+///   unless `lipics.knowledge.unused-notions` is false
 ///
 ///
 ///
 /// TODO:
 /// - [ ] Add debug informations
-/// - [ ] Test
-/// - [ ] Add information back to the metadata (introduced, unknown, backrefs)
-
-use std::hash::Hash;
-use pandoc_ast::{Inline, MetaValue};
-use std::collections::{HashMap, BTreeMap};
+/// - [x] Test
+/// - [x] Add information back to the metadata (introduced, unknown, backrefs)
+use nom::bytes::complete::{tag, take_till};
+use nom::character::complete::{char, multispace0};
+use nom::combinator::opt;
+use nom::sequence::delimited;
+use nom::IResult;
+use pandoc_ast::{Block, Inline, MathType, MetaValue, MutVisitor};
+use std::collections::{HashMap, HashSet, BTreeMap};
+use std::fmt;
 
+use crate::inflections::{english_rules, InflectionRule};
 use crate::utils;
 
 /// Create a newtype for "knowledges-ids"
 /// to avoid confusion with actual integers
 #[derive(Debug,Copy,Clone,PartialEq,Eq,Hash)]
-pub struct KnowledgeId(u16);
+pub struct KnowledgeId(usize);
 
 
 /// The knowledge commands that can be issued
@@ -36,6 +52,15 @@ pub enum KnowledgeCommandKind {
     Ref, 
 }
 
+impl KnowledgeCommandKind {
+    fn name(&self) -> &'static str {
+        match self {
+            KnowledgeCommandKind::Intro   => "intro",
+            KnowledgeCommandKind::Reintro => "reintro",
+            KnowledgeCommandKind::Ref     => "ref",
+        }
+    }
+}
 
 /// An internal representation of the knowledge command
 /// to be issued. This is language agnostic.
@@ -51,6 +76,110 @@ pub struct KnowledgeCommand {
     name: Option<String>,
     /// An optional specified scope for the knowledge
     scope: Option<String>,
+    /// Where the command appears in the document
+    location: KnowledgeLocation,
+    /// The inflection rule used to resolve the command
+    inflection: Option<String>,
+    /// The global knowledge hidden by an inherited scope
+    shadowed: Option<String>,
+    /// The synonym of the knowledge base that matched,
+    /// that may differ by its case or its inflection
+    matched: Option<KnowledgeSynonym>,
+}
+
+impl KnowledgeCommand {
+    /// Records where the command appears in the document
+    pub fn locate(&mut self, location: KnowledgeLocation) {
+        self.location = location;
+    }
+
+    /// The text of the command (without formatting)
+    pub fn text(&self) -> String {
+        utils::stringify_inlines(&self.content).split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// The synonym referred to by the command
+    fn synonym(&self) -> KnowledgeSynonym {
+        match (&self.scope, &self.name) {
+            (Some(scope), Some(name)) => KnowledgeSynonym::Scoped(vec![Inline::Str(name.clone())], scope.clone()),
+            (Some(scope), None)       => KnowledgeSynonym::Scoped(self.content.clone(), scope.clone()),
+            (None       , Some(name)) => KnowledgeSynonym::Global(vec![Inline::Str(name.clone())]),
+            (None       , None)       => KnowledgeSynonym::Global(self.content.clone()),
+        }
+    }
+}
+
+/// Where a knowledge command appears in the document,
+/// to give some context in diagnostics.
+#[derive(Debug,Clone,Default)]
+pub struct KnowledgeLocation {
+    /// The order of the command in the document
+    pub position: usize,
+    /// The title of the enclosing section
+    pub section: Option<String>,
+    /// Some text around the command
+    pub snippet: String,
+    /// The text of the enclosing block
+    pub context: String,
+    /// The `kl-scope` of the enclosing divs
+    /// and sections, innermost first
+    pub scopes: Vec<String>,
+}
+
+impl KnowledgeLocation {
+    /// Locates `text` inside of the `context` text of the
+    /// enclosing block, keeping a few words around it
+    pub fn new(position: usize, section: Option<String>, scopes: Vec<String>, context: &str, text: &str) -> KnowledgeLocation {
+        const WINDOW: usize = 30;
+        let context = context.split_whitespace().collect::<Vec<_>>().join(" ");
+        let snippet = match context.find(text) {
+            Some(i) => {
+                let before : Vec<char> = context[..i].chars().collect();
+                let after : Vec<char> = context[i + text.len()..].chars().collect();
+                let mut snippet = String::new();
+                if before.len() > WINDOW {
+                    snippet.push('…');
+                }
+                snippet.extend(&before[before.len().saturating_sub(WINDOW)..]);
+                snippet.push_str(text);
+                snippet.extend(after.iter().take(WINDOW));
+                if after.len() > WINDOW {
+                    snippet.push('…');
+                }
+                snippet
+            }
+            None => text.to_string(),
+        };
+        KnowledgeLocation { position, section, snippet, context, scopes }
+    }
+
+    /// The sentence of the enclosing block containing `text`,
+    /// or the whole block if it cannot be found
+    pub fn sentence(&self, text: &str) -> String {
+        let mut start = 0;
+        for (i, c) in self.context.char_indices() {
+            let end = i + c.len_utf8();
+            let boundary = matches!(c, '.' | '!' | '?')
+                && self.context[end..].starts_with(' ');
+            if boundary || end == self.context.len() {
+                let sentence = self.context[start..end].trim();
+                if sentence.contains(text) {
+                    return sentence.to_string();
+                }
+                start = end;
+            }
+        }
+        self.context.clone()
+    }
+}
+
+impl fmt::Display for KnowledgeLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.section {
+            Some(section) => write!(f, "in section \"{}\": \"{}\"", section, self.snippet),
+            None => write!(f, "before any section: \"{}\"", self.snippet),
+        }
+    }
 }
 
 
@@ -61,44 +190,289 @@ pub enum KnowledgeSynonym {
 }
 
 
-impl KnowledgeSynonym {
-    pub fn to_string(&self) -> String {
+impl fmt::Display for KnowledgeSynonym {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             KnowledgeSynonym::Global(i) => {
-                utils::stringify_inlines(&i)
+                write!(f, "{}", utils::stringify_inlines(i))
             }
             KnowledgeSynonym::Scoped(i,s) => {
-                format!("{}@{}", utils::stringify_inlines(&i), s)
+                write!(f, "{}@{}", utils::stringify_inlines(i), s)
             }
         }
     }
 }
 
+impl KnowledgeSynonym {
+    /// The key used to match this synonym:
+    /// its text without formatting, with collapsed
+    /// whitespace, and lowercase when `fold` is set.
+    pub fn key(&self, fold: bool) -> KnowledgeKey {
+        let (inlines, scope) = match self {
+            KnowledgeSynonym::Global(i) => (i, None),
+            KnowledgeSynonym::Scoped(i, s) => (i, Some(s.clone())),
+        };
+        let text = utils::stringify_inlines(inlines).split_whitespace().collect::<Vec<_>>().join(" ");
+        let text = if fold { text.to_lowercase() } else { text };
+        KnowledgeKey { text, scope, folded: fold }
+    }
+}
+
+/// The canonical form of a synonym, used to
+/// match knowledge commands against the knowledge base.
+/// Scoped and global synonyms never match each other.
+#[derive(Debug,Clone,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct KnowledgeKey {
+    text: String,
+    scope: Option<String>,
+    /// Folded keys only match the synonyms
+    /// of case-insensitive knowledges
+    folded: bool,
+}
 
-impl PartialEq for KnowledgeSynonym {
-    fn eq(&self, other: &Self) -> bool {
-        format!("{:?}", self) == format!("{:?}", other)
+impl fmt::Display for KnowledgeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.scope {
+            Some(scope) => write!(f, "{}@{}", self.text, scope),
+            None => write!(f, "{}", self.text),
+        }
     }
 }
 
-impl Eq for KnowledgeSynonym {}
+/// The display options of a knowledge,
+/// as in the `display` field of knowledge.schema.json
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum KnowledgeDisplay {
+    Italic,
+    Bold,
+    /// Medium weight: no bold face even in bold context
+    Md,
+    SmallCaps,
+    Lowercase,
+    Uppercase,
+}
 
-impl Hash for KnowledgeSynonym {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+impl TryFrom<&str> for KnowledgeDisplay {
+    type Error = ();
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "italic"    => Ok(KnowledgeDisplay::Italic),
+            "bold"      => Ok(KnowledgeDisplay::Bold),
+            "md"        => Ok(KnowledgeDisplay::Md),
+            "smallcaps" => Ok(KnowledgeDisplay::SmallCaps),
+            "lowercase" => Ok(KnowledgeDisplay::Lowercase),
+            "uppercase" => Ok(KnowledgeDisplay::Uppercase),
+            _ => Err(()),
+        }
+    }
+}
+
+impl KnowledgeDisplay {
+    fn name(&self) -> &'static str {
         match self {
-            KnowledgeSynonym::Global(i) => {
-                format!("{:?}", i).hash(state);
-            }
-            KnowledgeSynonym::Scoped(i,s) => {
-                format!("{:?}@{:?}", i, s).hash(state);
-            }
+            KnowledgeDisplay::Italic    => "italic",
+            KnowledgeDisplay::Bold      => "bold",
+            KnowledgeDisplay::Md        => "md",
+            KnowledgeDisplay::SmallCaps => "smallcaps",
+            KnowledgeDisplay::Lowercase => "lowercase",
+            KnowledgeDisplay::Uppercase => "uppercase",
+        }
+    }
+
+    /// The corresponding option of the knowledge package
+    fn latex_option(&self) -> &'static str {
+        match self {
+            KnowledgeDisplay::Bold => "boldface",
+            _ => self.name(),
+        }
+    }
+
+    /// The LaTeX macro producing this display
+    fn latex_macro(&self) -> &'static str {
+        match self {
+            KnowledgeDisplay::Italic    => "\\textit",
+            KnowledgeDisplay::Bold      => "\\textbf",
+            KnowledgeDisplay::Md        => "\\textmd",
+            KnowledgeDisplay::SmallCaps => "\\textsc",
+            KnowledgeDisplay::Lowercase => "\\MakeLowercase",
+            KnowledgeDisplay::Uppercase => "\\MakeUppercase",
         }
     }
 }
 
+/// Changes the case of the text of inlines
+struct CaseVisitor(KnowledgeDisplay);
+
+impl MutVisitor for CaseVisitor {
+    fn visit_inline(&mut self, inline: &mut Inline) {
+        if let Inline::Str(s) = inline {
+            *s = match self.0 {
+                KnowledgeDisplay::Lowercase => s.to_lowercase(),
+                KnowledgeDisplay::Uppercase => s.to_uppercase(),
+                _ => s.clone(),
+            };
+        }
+        self.walk_inline(inline);
+    }
+}
+
+/// A knowledge, following knowledge.schema.json
 #[derive(Debug,Clone)]
 pub struct KnowledgeEntry {
     synonyms: Vec<KnowledgeSynonym>,
+    /// Whether this knowledge is a notion
+    notion: bool,
+    /// A display name for this knowledge
+    name: Option<String>,
+    /// The Wikidata identifier for this knowledge
+    wikidata: Option<String>,
+    description: Option<String>,
+    urls: Vec<String>,
+    /// How this knowledge is indexed in LaTeX
+    index: Option<String>,
+    color: Option<String>,
+    display: Vec<KnowledgeDisplay>,
+    /// Replaces the instances of the knowledge
+    placeholder: Option<String>,
+    /// Whether the synonyms are matched with their case,
+    /// otherwise `[Word]{.ref}` matches the synonym `word`
+    case_sensitive: bool,
+}
+
+impl KnowledgeEntry {
+    /// The name of the knowledge, defaulting to its first synonym
+    pub fn display_name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => self.synonyms.first().map(|s| s.to_string()).unwrap_or_default(),
+        }
+    }
+
+    /// The synonym of the knowledge that `synonym` matches,
+    /// exactly or up to its case
+    fn synonym_matching(&self, synonym: &KnowledgeSynonym) -> Option<&KnowledgeSynonym> {
+        self.synonyms.iter().find(|s| s.key(false) == synonym.key(false))
+            .or_else(|| self.synonyms.iter().find(|s| !self.case_sensitive && s.key(true) == synonym.key(true)))
+    }
+
+    /// The external page of the knowledge:
+    /// its first url, or its Wikidata page
+    pub fn url(&self) -> Option<String> {
+        self.urls.first().cloned().or_else(|| {
+            self.wikidata.as_ref().map(|w| format!("https://www.wikidata.org/wiki/{}", w))
+        })
+    }
+
+    /// The options of the `\knowledge` declaration
+    pub fn latex_options(&self) -> Vec<String> {
+        let mut options = vec![];
+        if self.notion {
+            options.push("notion".to_string());
+        }
+        if let Some(url) = self.url() {
+            // hyperref wants these two escaped in urls
+            options.push(format!("url={{{}}}", url.replace('%', "\\%").replace('#', "\\#")));
+        }
+        if let Some(color) = &self.color {
+            options.push(format!("color={{{}}}", color));
+        }
+        if let Some(index) = &self.index {
+            options.push(format!("index={{{}}}", utils::escape_index(index)));
+        }
+        if let Some(text) = &self.placeholder {
+            options.push(format!("text={{{}}}", utils::escape_latex(text)));
+        }
+        options.extend(self.display.iter().map(|d| d.latex_option().to_string()));
+        options
+    }
+
+    /// The `\knowledge` declaration of the entry,
+    /// one synonym per line
+    pub fn to_latex(&self) -> String {
+        let mut latex = format!("\\knowledge{{{}}}\n", self.latex_options().join(","));
+        for syn in &self.synonyms {
+            let line = match syn {
+                KnowledgeSynonym::Global(i) => synonym_to_latex(i),
+                KnowledgeSynonym::Scoped(i, scope) => format!("{}@{}", synonym_to_latex(i), utils::escape_latex(scope)),
+            };
+            latex.push_str(&format!(" | {}\n", line));
+        }
+        latex
+    }
+
+    /// The content of a command, with the placeholder
+    /// text and the case of the knowledge applied
+    fn content(&self, content: Vec<Inline>) -> Vec<Inline> {
+        let mut content = match &self.placeholder {
+            Some(text) => vec![Inline::Str(text.clone())],
+            None => content,
+        };
+        for d in &self.display {
+            if *d == KnowledgeDisplay::Lowercase || *d == KnowledgeDisplay::Uppercase {
+                CaseVisitor(*d).walk_vec_inline(&mut content);
+            }
+        }
+        content
+    }
+
+    /// Applies the display options to pandoc inlines
+    fn style_pandoc(&self, content: Vec<Inline>) -> Vec<Inline> {
+        let mut content = self.content(content);
+        for d in &self.display {
+            content = match d {
+                KnowledgeDisplay::Italic    => vec![Inline::Emph(content)],
+                KnowledgeDisplay::Bold      => vec![Inline::Strong(content)],
+                KnowledgeDisplay::SmallCaps => vec![Inline::SmallCaps(content)],
+                _ => content,
+            };
+        }
+        content
+    }
+
+    /// Applies the display options and the colour in LaTeX
+    fn style_latex(&self, content: Vec<Inline>) -> Vec<Inline> {
+        let format = pandoc_ast::Format("latex".to_string());
+        let mut content = self.content(content);
+        let wrap = |content: Vec<Inline>, command: String| {
+            let mut inlines = vec![Inline::RawInline(format.clone(), format!("{}{{", command))];
+            inlines.extend(content);
+            inlines.push(Inline::RawInline(format.clone(), "}".to_string()));
+            inlines
+        };
+        for d in &self.display {
+            if *d != KnowledgeDisplay::Lowercase && *d != KnowledgeDisplay::Uppercase {
+                content = wrap(content, d.latex_macro().to_string());
+            }
+        }
+        if let Some(color) = &self.color {
+            content = wrap(content, format!("\\textcolor{{{}}}", color));
+        }
+        content
+    }
+
+    /// The attributes of a resolved knowledge command
+    /// for pandoc: display classes and colour
+    fn attributes(&self, classes: &mut Vec<String>, keyvals: &mut Vec<(String,String)>) {
+        if self.notion {
+            classes.push("kl-notion".to_string());
+        }
+        classes.extend(self.display.iter().map(|d| format!("kl-{}", d.name())));
+        if let Some(color) = &self.color {
+            keyvals.push(("style".to_string(), format!("color: {}", color)));
+        }
+    }
+}
+
+/// The text of a synonym in a `\knowledge` declaration:
+/// math and raw LaTeX are kept, the rest is escaped, and
+/// the separators `|` and `@` of the knowledge package are braced.
+fn synonym_to_latex(inlines: &[Inline]) -> String {
+    inlines.iter().map(|i| match i {
+        Inline::Math(_, s) => format!("${}$", s),
+        Inline::RawInline(pandoc_ast::Format(f), s) if f == "latex" || f == "tex" => s.clone(),
+        Inline::Space | Inline::SoftBreak | Inline::LineBreak => " ".to_string(),
+        _ => utils::escape_latex(&utils::stringify(i)).replace('|', "{|}").replace('@', "{@}"),
+    }).collect()
 }
 
 #[derive(Debug,Clone)]
@@ -106,7 +480,10 @@ pub struct KnowledgeBase {
     /// Resolve a label to a knowledge entry
     forward:  Vec<KnowledgeEntry>,
     /// Resolve a synonym to a canonical label
-    canonize: HashMap<KnowledgeSynonym, KnowledgeId>,
+    canonize: HashMap<KnowledgeKey, KnowledgeId>,
+    /// The anchor of each knowledge, that does not
+    /// depend on the order of the knowledges
+    anchors: Vec<String>,
 }
 
 #[derive(Debug,Clone)]
@@ -120,18 +497,268 @@ pub struct KnowledgeResolver {
     introduced: Vec<(KnowledgeId,KnowledgeCommand)>,
     /// Unknown knowledges
     unknown: Vec<KnowledgeCommand>,
+    /// The rules used when a reference does not match a synonym
+    inflections: Vec<InflectionRule>,
+    /// The position of the first introduction of
+    /// each knowledge, found during the collect pass
+    intros: HashMap<KnowledgeId, usize>,
+    /// Whether the introductions carry their index
+    /// entry, only when the output is LaTeX
+    latex_index: bool,
 }
 
 impl KnowledgeResolver {
     pub fn new(db: KnowledgeBase) -> KnowledgeResolver {
-        KnowledgeResolver { knowledge: db, backrefs: vec![], introduced: vec![], unknown: vec![] }
+        KnowledgeResolver::with_inflections(db, english_rules())
+    }
+
+    pub fn with_inflections(db: KnowledgeBase, inflections: Vec<InflectionRule>) -> KnowledgeResolver {
+        KnowledgeResolver { knowledge: db, backrefs: vec![], introduced: vec![], unknown: vec![], inflections, intros: HashMap::new(), latex_index: false }
+    }
+
+    /// Adds the index entries to the introductions
+    /// rendered as pandoc spans, for the LaTeX output
+    pub fn with_latex_index(self, latex_index: bool) -> KnowledgeResolver {
+        KnowledgeResolver { latex_index, ..self }
+    }
+
+    /// Records the introductions during the collect pass,
+    /// before any knowledge command is rewritten
+    pub fn collect(&mut self, mut kl: KnowledgeCommand) {
+        if let KnowledgeCommandKind::Intro = kl.kind {
+            if let Some((kid, _)) = resolve_inflected(&self.knowledge, &self.inflections, &mut kl) {
+                self.intros.entry(kid).or_insert(kl.location.position);
+            }
+        }
+    }
+
+    /// Describes a knowledge command for the report
+    fn command_to_meta(&self, kid: Option<KnowledgeId>, kl: &KnowledgeCommand) -> MetaValue {
+        let mut m = BTreeMap::new();
+        let mut insert = |k: &str, v: MetaValue| { m.insert(k.to_string(), Box::new(v)); };
+        insert("kind", MetaValue::MetaString(kl.kind.name().to_string()));
+        insert("text", MetaValue::MetaInlines(kl.content.clone()));
+        if let Some(kid) = kid {
+            insert("id", MetaValue::MetaString(self.knowledge.anchor(kid).to_string()));
+            if let Some(entry) = self.knowledge.forward.get(kid.0) {
+                insert("knowledge", MetaValue::MetaString(entry.display_name()));
+            }
+        }
+        if !kl.ident.is_empty() {
+            insert("anchor", MetaValue::MetaString(kl.ident.clone()));
+        }
+        if let Some(name) = &kl.name {
+            insert("name", MetaValue::MetaString(name.clone()));
+        }
+        if let Some(scope) = &kl.scope {
+            insert("scope", MetaValue::MetaString(scope.clone()));
+        }
+        if let Some(inflection) = &kl.inflection {
+            insert("inflection", MetaValue::MetaString(inflection.clone()));
+        }
+        if let Some(global) = &kl.shadowed {
+            insert("shadows", MetaValue::MetaString(global.clone()));
+        }
+        MetaValue::MetaMap(m)
+    }
+
+    /// The results of the resolution, to be stored
+    /// in `lipics.knowledge-report`:
+    /// the introduced knowledges, the references
+    /// and the unknown knowledges. They are recorded
+    /// in every mode, including the knowledge package one.
+    pub fn report(&self) -> MetaValue {
+        let introduced = self.introduced.iter().map(|(kid, kl)| self.command_to_meta(Some(*kid), kl)).collect();
+        let references = self.backrefs.iter().map(|(kid, kl)| self.command_to_meta(Some(*kid), kl)).collect();
+        let unknown = self.unknown.iter().map(|kl| self.command_to_meta(None, kl)).collect();
+        MetaValue::MetaMap(BTreeMap::from([
+            ("introduced".to_string(), Box::new(MetaValue::MetaList(introduced))),
+            ("references".to_string(), Box::new(MetaValue::MetaList(references))),
+            ("unknown".to_string(), Box::new(MetaValue::MetaList(unknown))),
+        ]))
+    }
+}
+
+/// The problems found while resolving knowledges
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum KnowledgeDiagnosticKind {
+    /// An unknown knowledge, with the closest synonyms
+    Unknown(Vec<String>),
+    RefBeforeIntro,
+    IntroducedTwice,
+    NeverReferenced,
+    NeverIntroduced,
+    /// A scoped knowledge hiding a global one
+    Shadowed(String),
+}
+
+#[derive(Debug,Clone)]
+pub struct KnowledgeDiagnostic {
+    pub kind: KnowledgeDiagnosticKind,
+    /// The knowledge (or the unknown text)
+    pub knowledge: String,
+    pub location: Option<KnowledgeLocation>,
+    /// The inflection rule that resolved the command
+    pub inflection: Option<String>,
+}
+
+impl fmt::Display for KnowledgeDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            KnowledgeDiagnosticKind::Unknown(_)      => write!(f, "unknown knowledge `{}`", self.knowledge)?,
+            KnowledgeDiagnosticKind::RefBeforeIntro  => write!(f, "knowledge `{}` is referenced before its introduction", self.knowledge)?,
+            KnowledgeDiagnosticKind::IntroducedTwice => write!(f, "knowledge `{}` is introduced twice", self.knowledge)?,
+            KnowledgeDiagnosticKind::NeverReferenced => write!(f, "knowledge `{}` is introduced but never referenced", self.knowledge)?,
+            KnowledgeDiagnosticKind::NeverIntroduced => match self.location {
+                Some(_) => write!(f, "knowledge `{}` is referenced but never introduced", self.knowledge)?,
+                None => write!(f, "knowledge `{}` is declared but never introduced", self.knowledge)?,
+            },
+            KnowledgeDiagnosticKind::Shadowed(global) => write!(f, "ambiguous knowledge: `{}` shadows the global `{}`", self.knowledge, global)?,
+        }
+        if let Some(location) = &self.location {
+            write!(f, " {}", location)?;
+        }
+        if let Some(inflection) = &self.inflection {
+            write!(f, " (resolved by the inflection rule `{}`)", inflection)?;
+        }
+        if let KnowledgeDiagnosticKind::Unknown(suggestions) = &self.kind {
+            if !suggestions.is_empty() {
+                let suggestions : Vec<String> = suggestions.iter().map(|s| format!("`{}`", s)).collect();
+                write!(f, " (did you mean {}?)", suggestions.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The messages of the diagnostics as printed by the filter:
+/// plain warnings, or errors failing the build in strict mode
+pub fn check_diagnostics(diagnostics: &[KnowledgeDiagnostic], strict: bool) -> Result<Vec<String>, Vec<String>> {
+    if strict && !diagnostics.is_empty() {
+        Err(diagnostics.iter().map(|d| format!("error: {}", d)).collect())
+    } else {
+        Ok(diagnostics.iter().map(|d| d.to_string()).collect())
+    }
+}
+
+impl KnowledgeResolver {
+    /// The synonyms closest to an unknown knowledge
+    fn suggestions(&self, kl: &KnowledgeCommand) -> Vec<String> {
+        let text = match &kl.name {
+            Some(name) => name.to_lowercase(),
+            None => kl.text().to_lowercase(),
+        };
+        let threshold = std::cmp::max(1, text.chars().count() / 3);
+        let mut candidates : Vec<(usize, String)> = self.knowledge.canonize.keys()
+            .map(|syn| syn.to_string())
+            .map(|syn| (utils::edit_distance(&text, &syn.to_lowercase()), syn))
+            .filter(|(d, _)| *d <= threshold)
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        candidates.into_iter().take(3).map(|(_, syn)| syn).collect()
+    }
+
+    /// Checks the use of the knowledges once
+    /// the whole document has been resolved.
+    /// Notions that are neither introduced nor referenced
+    /// are only reported when `unused_notions` is set.
+    pub fn diagnostics(&self, unused_notions: bool) -> Vec<KnowledgeDiagnostic> {
+        let mut diagnostics = vec![];
+        let name = |kid: KnowledgeId| self.knowledge.forward[kid.0].display_name();
+
+        for kl in &self.unknown {
+            diagnostics.push(KnowledgeDiagnostic {
+                kind: KnowledgeDiagnosticKind::Unknown(self.suggestions(kl)),
+                knowledge: kl.synonym().key(false).to_string(),
+                location: Some(kl.location.clone()),
+                inflection: None,
+            });
+        }
+
+        for (kid, kl) in self.introduced.iter().chain(&self.backrefs) {
+            if let Some(global) = &kl.shadowed {
+                diagnostics.push(KnowledgeDiagnostic {
+                    kind: KnowledgeDiagnosticKind::Shadowed(global.clone()),
+                    knowledge: name(*kid),
+                    location: Some(kl.location.clone()),
+                    inflection: kl.inflection.clone(),
+                });
+            }
+        }
+
+        let mut intros : HashMap<KnowledgeId, Vec<&KnowledgeCommand>> = HashMap::new();
+        for (kid, kl) in &self.introduced {
+            intros.entry(*kid).or_default().push(kl);
+        }
+        let mut refs : HashMap<KnowledgeId, Vec<&KnowledgeCommand>> = HashMap::new();
+        for (kid, kl) in &self.backrefs {
+            refs.entry(*kid).or_default().push(kl);
+        }
+
+        for (i, entry) in self.knowledge.forward.iter().enumerate() {
+            let kid = KnowledgeId(i);
+            let intro = intros.get(&kid);
+            let refs = refs.get(&kid);
+            match intro {
+                None if !entry.notion => {}
+                None => match refs {
+                    Some(refs) => diagnostics.push(KnowledgeDiagnostic {
+                        kind: KnowledgeDiagnosticKind::NeverIntroduced,
+                        knowledge: name(kid),
+                        location: Some(refs[0].location.clone()),
+                        inflection: refs[0].inflection.clone(),
+                    }),
+                    // the unused entries of a shared glossary can be silenced
+                    None if unused_notions => diagnostics.push(KnowledgeDiagnostic {
+                        kind: KnowledgeDiagnosticKind::NeverIntroduced,
+                        knowledge: name(kid),
+                        location: None,
+                        inflection: None,
+                    }),
+                    None => {}
+                },
+                Some(intro) => {
+                    if let Some(second) = intro.get(1) {
+                        diagnostics.push(KnowledgeDiagnostic {
+                            kind: KnowledgeDiagnosticKind::IntroducedTwice,
+                            knowledge: name(kid),
+                            location: Some(second.location.clone()),
+                            inflection: second.inflection.clone(),
+                        });
+                    }
+                    match refs {
+                        None => diagnostics.push(KnowledgeDiagnostic {
+                            kind: KnowledgeDiagnosticKind::NeverReferenced,
+                            knowledge: name(kid),
+                            location: Some(intro[0].location.clone()),
+                            inflection: intro[0].inflection.clone(),
+                        }),
+                        Some(refs) => {
+                            let first = intro[0].location.position;
+                            if let Some(early) = refs.iter().find(|r| r.location.position < first) {
+                                diagnostics.push(KnowledgeDiagnostic {
+                                    kind: KnowledgeDiagnosticKind::RefBeforeIntro,
+                                    knowledge: name(kid),
+                                    location: Some(early.location.clone()),
+                                    inflection: early.inflection.clone(),
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        diagnostics
     }
 }
 
 /// Resolve a label to a knowledge if possible
+/// trying the exact text first, then the case-folded one
 fn resolve_knowledge<'a>(kdb : &'a KnowledgeBase, l : &KnowledgeSynonym) -> Option<(KnowledgeId, &'a KnowledgeEntry)> {
-    match kdb.canonize.get(l) {
-        Some(id) => match kdb.forward.get(id.0 as usize) {
+    let found = kdb.canonize.get(&l.key(false)).or_else(|| kdb.canonize.get(&l.key(true)));
+    match found {
+        Some(id) => match kdb.forward.get(id.0) {
             Some(entry) => Some((*id, entry)),
             None => None,
         }
@@ -139,6 +766,67 @@ fn resolve_knowledge<'a>(kdb : &'a KnowledgeBase, l : &KnowledgeSynonym) -> Opti
     }
 }
 
+/// Gives to a command without `scope=` the innermost scope
+/// of its location that defines its text, like the scopes
+/// of the knowledge package. The global knowledge that the
+/// scoped one hides, if any, is recorded in the command.
+fn inherit_scope(kdb : &KnowledgeBase, rules : &[InflectionRule], kl : &mut KnowledgeCommand) {
+    if kl.scope.is_some() {
+        return;
+    }
+    for scope in &kl.location.scopes {
+        let mut scoped = kl.clone();
+        scoped.scope = Some(scope.clone());
+        if let Some((kid, _)) = resolve_synonym(kdb, rules, &mut scoped) {
+            if let Some((global, entry)) = resolve_synonym(kdb, rules, &mut kl.clone()) {
+                if global != kid {
+                    scoped.shadowed = Some(entry.display_name());
+                }
+            }
+            *kl = scoped;
+            return;
+        }
+    }
+}
+
+/// Resolve a knowledge command in its inherited scopes,
+/// then globally (see `inherit_scope` and `resolve_synonym`)
+fn resolve_inflected<'a>(kdb : &'a KnowledgeBase, rules : &[InflectionRule], kl : &mut KnowledgeCommand) -> Option<(KnowledgeId, &'a KnowledgeEntry)> {
+    inherit_scope(kdb, rules, kl);
+    resolve_synonym(kdb, rules, kl)
+}
+
+/// Resolve a knowledge command, using the inflection
+/// rules when its text does not match any synonym.
+/// The rule used is recorded in the command.
+fn resolve_synonym<'a>(kdb : &'a KnowledgeBase, rules : &[InflectionRule], kl : &mut KnowledgeCommand) -> Option<(KnowledgeId, &'a KnowledgeEntry)> {
+    let synonym = kl.synonym();
+    if let Some(found) = resolve_knowledge(kdb, &synonym) {
+        kl.matched = found.1.synonym_matching(&synonym).cloned();
+        return Some(found);
+    }
+    // an explicit name is never inflected
+    if kl.name.is_some() {
+        return None;
+    }
+    let text = kl.text();
+    for rule in rules {
+        if let Some(inflected) = rule.apply(&text) {
+            let inflected = vec![Inline::Str(inflected)];
+            let synonym = match &kl.scope {
+                Some(scope) => KnowledgeSynonym::Scoped(inflected, scope.clone()),
+                None => KnowledgeSynonym::Global(inflected),
+            };
+            if let Some(found) = resolve_knowledge(kdb, &synonym) {
+                kl.inflection = Some(rule.name.clone());
+                kl.matched = found.1.synonym_matching(&synonym).cloned();
+                return Some(found);
+            }
+        }
+    }
+    None
+}
+
 fn classes_to_knowledge_kind(classes: &[String]) -> Option<KnowledgeCommandKind> {
     if classes.contains(&"intro".to_string()) {
         Some(KnowledgeCommandKind::Intro)
@@ -164,6 +852,12 @@ fn keyvals_to_knowledge_command(keyvals: &[(String,String)]) -> (Option<String>,
     (name, scope)
 }
 
+/// The knowledge scope set by a div or a header
+/// ::: {kl-scope=automata} -> automata
+pub fn attr_scope(keyvals: &[(String,String)]) -> Option<String> {
+    keyvals.iter().find(|(k, _)| k == "kl-scope").map(|(_, v)| v.clone())
+}
+
 /// Parses a span into a knowledge command if possible
 /// - [knowledge]{.intro} -> KnowledgeCommand { kind: Intro, content: [knowledge] }
 /// - [knowledge]{.reintro} -> KnowledgeCommand { kind: Reintro, content: [knowledge] }
@@ -173,47 +867,139 @@ pub fn span_to_knowledge(span : &Inline) -> Option<KnowledgeCommand> {
         Inline::Span((ident, classes, keyvals), inlines) => {
             let (name, scope) = keyvals_to_knowledge_command(keyvals);
             let kind = classes_to_knowledge_kind(classes)?;
-            Some(KnowledgeCommand { ident: ident.clone(), kind, content: inlines.clone(), name, scope })
+            Some(KnowledgeCommand { ident: ident.clone(), kind, content: inlines.clone(), name, scope, location: KnowledgeLocation::default(), inflection: None, shadowed: None, matched: None })
         }
         _ => None,
     }
 }
 
-/// Transforms a knowledge command into the corresponding
-/// LaTeX code.
-///
-/// This function delegates the actual resolution to the
-/// `knowledge` package in LaTeX, and thus requires a
-/// specific preamble in the document.
-pub fn knowledge_to_latex(knowledge: KnowledgeCommand) -> Vec<Inline> {
-    let format = pandoc_ast::Format("latex".to_string());
-    let mut inlines = vec![];
-    let params = match (knowledge.scope, knowledge.name) {
+/// A piece of a formula: LaTeX code, or a
+/// knowledge reference with its LaTeX code
+#[derive(Debug,Clone)]
+pub enum MathPart {
+    Text(String),
+    Knowledge(String, Box<KnowledgeCommand>),
+}
+
+/// Parses a knowledge reference inside of a formula,
+/// with the syntax of the knowledge package
+/// \kl(scope)[name]{text} -> (scope, name, text)
+fn math_kl(input: &str) -> IResult<&str, (Option<&str>, Option<&str>, &str)> {
+    let (input, _) = tag("\\kl")(input)?;
+    let (input, scope) = opt(delimited(char('('), take_till(|c| c == ')'), char(')')))(input)?;
+    let (input, name) = opt(delimited(char('['), take_till(|c| c == ']'), char(']')))(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, text) = utils::braced(input)?;
+    Ok((input, (scope, name, text)))
+}
+
+/// Splits a formula around its knowledge references,
+/// if it has any
+/// \kl{\mathsf{Reg}} \subseteq X -> [Knowledge(\mathsf{Reg}), Text( \subseteq X)]
+pub fn math_to_knowledges(math: &str) -> Option<Vec<MathPart>> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut input = math;
+    while let Some(i) = input.find("\\kl") {
+        text.push_str(&input[..i]);
+        match math_kl(&input[i..]) {
+            Ok((rest, (scope, name, content))) => {
+                if !text.is_empty() {
+                    parts.push(MathPart::Text(std::mem::take(&mut text)));
+                }
+                let source = input[i..input.len() - rest.len()].to_string();
+                parts.push(MathPart::Knowledge(source, Box::new(KnowledgeCommand {
+                    ident: String::new(),
+                    kind: KnowledgeCommandKind::Ref,
+                    content: vec![Inline::Math(MathType::InlineMath, content.to_string())],
+                    name: name.map(str::to_string),
+                    scope: scope.map(str::to_string),
+                    location: KnowledgeLocation::default(),
+                    inflection: None,
+                    shadowed: None,
+                    matched: None,
+                })));
+                input = rest;
+            }
+            // another macro, like \klass
+            Err(_) => {
+                text.push_str("\\kl");
+                input = &input[i + 3..];
+            }
+        }
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    text.push_str(input);
+    if !text.is_empty() {
+        parts.push(MathPart::Text(text));
+    }
+    Some(parts)
+}
+
+/// The `(scope)[name]` parameters of a command of the
+/// knowledge package. Unless an explicit name is given,
+/// the name is the synonym that matched, when it differs
+/// from the text (e.g. by its case or its inflection),
+/// since the package only knows the declared synonyms.
+fn latex_parameters(kl: &KnowledgeCommand, text: &str) -> String {
+    let name = match (&kl.name, &kl.matched) {
+        (Some(name), _) => Some(name.clone()),
+        (None, Some(KnowledgeSynonym::Global(i) | KnowledgeSynonym::Scoped(i, _))) => {
+            Some(synonym_to_latex(i)).filter(|name| name != text)
+        }
+        (None, None) => None,
+    };
+    match (&kl.scope, name) {
         (Some(scope),Some(name)) => format!("({})[{}]", scope, name),
         (Some(scope),None) => format!("({})", scope),
         (None,Some(name)) => format!("[{}]", name),
         (None,None) => "".to_string(),
-    };
-    match knowledge.kind {
-        KnowledgeCommandKind::Intro => {
-            inlines.push(Inline::RawInline(format.clone(), format!("\\intro{}{{", params)));
-            inlines.extend(knowledge.content);
-            inlines.push(Inline::RawInline(format.clone(), "}".to_string()));
-        }
-        KnowledgeCommandKind::Reintro => {
-            inlines.push(Inline::RawInline(format.clone(), format!("\\reintro{}{{", params)));
-            inlines.extend(knowledge.content);
-            inlines.push(Inline::RawInline(format.clone(), "}".to_string()));
-        }
-        KnowledgeCommandKind::Ref => {
-            inlines.push(Inline::RawInline(format.clone(), format!("\\kl{}{{", params)));
-            inlines.extend(knowledge.content);
-            inlines.push(Inline::RawInline(format.clone(), "}".to_string()));
-        }
     }
-    inlines 
 }
 
+/// Transforms a knowledge command into the corresponding
+/// LaTeX code.
+///
+/// This function delegates the display of the knowledges
+/// to the `knowledge` package in LaTeX, and thus requires a
+/// specific preamble in the document. The commands are
+/// still resolved, to point the package at the synonym
+/// that matched and to report the problems found.
+pub fn knowledge_to_latex(db: &mut KnowledgeResolver, mut kl: KnowledgeCommand) -> Vec<Inline> {
+    let format = pandoc_ast::Format("latex".to_string());
+    match resolve_inflected(&db.knowledge, &db.inflections, &mut kl) {
+        None => db.unknown.push(kl.clone()),
+        Some((kid, _)) => match kl.kind {
+            KnowledgeCommandKind::Intro => db.introduced.push((kid, kl.clone())),
+            KnowledgeCommandKind::Reintro => {}
+            KnowledgeCommandKind::Ref => db.backrefs.push((kid, kl.clone())),
+        },
+    }
+    let params = latex_parameters(&kl, &synonym_to_latex(&kl.content));
+    let command = match kl.kind {
+        KnowledgeCommandKind::Intro => "intro",
+        KnowledgeCommandKind::Reintro => "reintro",
+        KnowledgeCommandKind::Ref => "kl",
+    };
+    let mut inlines = vec![Inline::RawInline(format.clone(), format!("\\{}{}{{", command, params))];
+    inlines.extend(kl.content);
+    inlines.push(Inline::RawInline(format, "}".to_string()));
+    inlines
+}
+
+/// The default definitions of the macros of the fast LaTeX
+/// mode, with \providecommand so that the preamble of the
+/// document can still redefine them
+pub const FAST_LATEX_MACROS: &str = "\\providecommand{\\akldef}[2]{\\hypertarget{#1}{\\emph{#2}}}
+\\providecommand{\\aklredef}[2]{\\hyperlink{#1}{\\emph{#2}}}
+\\providecommand{\\aklref}[2]{\\hyperlink{#1}{#2}}
+\\providecommand{\\akldeclared}[2]{#2}
+\\providecommand{\\akldeferror}[1]{\\emph{#1}}
+\\providecommand{\\aklredeferror}[1]{\\emph{#1}}
+\\providecommand{\\aklreferror}[1]{#1}";
+
 /// Transforms a knowledge command into the corresponding
 /// LaTeX code. This *avoids* using the knowledge package,
 /// but still emits low-level LaTeX code that allows to
@@ -229,20 +1015,20 @@ pub fn knowledge_to_latex(knowledge: KnowledgeCommand) -> Vec<Inline> {
 /// \akldeferror{content}
 /// \aklreferror{content}
 /// \aklredeferror{content}
+/// \akldeclared{unique-id}{content}
+///
+/// where \akldeclared is a reference to a knowledge
+/// that is declared but never introduced in the document.
+///
+/// Default definitions are given by `FAST_LATEX_MACROS`.
 ///
 /// TODO: add the possibility to compute the backreferences
 /// and list introduced, duplicated, and unknown knowledges.
 ///
-pub fn knowledge_to_fast_latex(db : &mut KnowledgeResolver, kl: KnowledgeCommand) -> Vec<Inline> {
+pub fn knowledge_to_fast_latex(db : &mut KnowledgeResolver, mut kl: KnowledgeCommand) -> Vec<Inline> {
     let format = pandoc_ast::Format("latex".to_string());
-    let synonym = match (&kl.scope, &kl.name) { 
-        (Some(scope), Some(name)) => KnowledgeSynonym::Scoped(vec![Inline::Str(name.clone())], scope.clone()),
-        (Some(scope), None)       => KnowledgeSynonym::Scoped(kl.content.clone(), scope.clone()),
-        (None       , Some(name)) => KnowledgeSynonym::Global(vec![Inline::Str(name.clone())]),
-        (None       , None)       => KnowledgeSynonym::Global(kl.content.clone()),
-    };
     let mut inlines = vec![];
-    match resolve_knowledge(&db.knowledge, &synonym) {
+    match resolve_inflected(&db.knowledge, &db.inflections, &mut kl) {
         None => {
             // We do not have a knowledge entry: this is problematic
             // and we should issue a warning.
@@ -259,8 +1045,9 @@ pub fn knowledge_to_fast_latex(db : &mut KnowledgeResolver, kl: KnowledgeCommand
                 }
             }
         }
-        Some((kid, _)) => {
-            let kl_unique_id = format!("kl-{}", kid.0);
+        Some((kid, entry)) => {
+            let kl_unique_id = db.knowledge.anchor(kid).to_string();
+            let content = entry.style_latex(kl.content.clone());
             match kl.kind {
                 KnowledgeCommandKind::Intro => {
                     db.introduced.push((kid, kl.clone()));
@@ -270,10 +1057,22 @@ pub fn knowledge_to_fast_latex(db : &mut KnowledgeResolver, kl: KnowledgeCommand
                     inlines.push(Inline::RawInline(format.clone(), format!("\\aklredef{{{}}}{{", kl_unique_id)));
                 }
                 KnowledgeCommandKind::Ref => {
-                    inlines.push(Inline::RawInline(format.clone(), format!("\\aklref{{{}}}{{", kl_unique_id)));
                     db.backrefs.push((kid, kl.clone()));
+                    match entry.url() {
+                        Some(url) => inlines.push(Inline::RawInline(format.clone(), format!("\\href{{{}}}{{", url))),
+                        None if !db.intros.contains_key(&kid) => {
+                            inlines.push(Inline::RawInline(format.clone(), format!("\\akldeclared{{{}}}{{", kl_unique_id)))
+                        }
+                        None => inlines.push(Inline::RawInline(format.clone(), format!("\\aklref{{{}}}{{", kl_unique_id))),
+                    }
                 }
             }
+            inlines.extend(content);
+            inlines.push(Inline::RawInline(format.clone(), "}".to_string()));
+            if let (KnowledgeCommandKind::Intro, Some(index)) = (&kl.kind, &entry.index) {
+                inlines.push(Inline::RawInline(format.clone(), format!("\\index{{{}}}", utils::escape_index(index))));
+            }
+            return inlines;
         }
     }
     inlines.extend(kl.content);
@@ -287,13 +1086,7 @@ pub fn knowledge_to_fast_latex(db : &mut KnowledgeResolver, kl: KnowledgeCommand
 /// knowledge installed)
 ///
 pub fn knowledge_to_pandoc(db: &mut KnowledgeResolver, mut kl: KnowledgeCommand) -> Inline {
-    let synonym = match (&kl.scope, &kl.name) { 
-        (Some(scope), Some(name)) => KnowledgeSynonym::Scoped(vec![Inline::Str(name.clone())], scope.clone()),
-        (Some(scope), None)       => KnowledgeSynonym::Scoped(kl.content.clone(), scope.clone()),
-        (None       , Some(name)) => KnowledgeSynonym::Global(vec![Inline::Str(name.clone())]),
-        (None       , None)       => KnowledgeSynonym::Global(kl.content.clone()),
-    };
-    match resolve_knowledge(&db.knowledge, &synonym) {
+    match resolve_inflected(&db.knowledge, &db.inflections, &mut kl) {
         None => {
             // We do not have a knowledge entry: this is problematic
             // and we should issue a warning.
@@ -313,30 +1106,59 @@ pub fn knowledge_to_pandoc(db: &mut KnowledgeResolver, mut kl: KnowledgeCommand)
             }
         }
         Some((kid, entry)) => {
-            let kl_unique_id = format!("kl-{}", kid.0);
+            let kl_unique_id = db.knowledge.anchor(kid).to_string();
+            let content = entry.style_pandoc(kl.content.clone());
             match kl.kind {
                 KnowledgeCommandKind::Intro => {
-                    let attrs = (kl_unique_id,
-                                vec!["kl-intro".to_string(), "kl-defined".to_string()],
-                                vec![("kl".into(), kid.0.to_string())]);
+                    let mut classes = vec!["kl-intro".to_string(), "kl-defined".to_string()];
+                    let mut keyvals = vec![("kl".into(), kl_unique_id.clone())];
+                    entry.attributes(&mut classes, &mut keyvals);
                     db.introduced.push((kid, kl.clone()));
-                    Inline::Span(attrs, vec![Inline::Emph(kl.content)])
+                    let mut inlines = vec![Inline::Emph(content)];
+                    if let (true, Some(index)) = (db.latex_index, &entry.index) {
+                        inlines.push(Inline::RawInline(pandoc_ast::Format("latex".to_string()), format!("\\index{{{}}}", utils::escape_index(index))));
+                    }
+                    Inline::Span((kl_unique_id, classes, keyvals), inlines)
                 }
                 KnowledgeCommandKind::Reintro => {
-                    let attrs = ("".into(),
-                                 vec!["kl-reintro".to_string(), "kl-defined".to_string()],
-                                 vec![("kl".into(), kid.0.to_string())]);
-                    Inline::Span(attrs, vec![Inline::Emph(kl.content)])
+                    let mut classes = vec!["kl-reintro".to_string(), "kl-defined".to_string()];
+                    let mut keyvals = vec![("kl".into(), kl_unique_id.clone())];
+                    entry.attributes(&mut classes, &mut keyvals);
+                    Inline::Span(("".into(), classes, keyvals), vec![Inline::Emph(content)])
                 }
                 KnowledgeCommandKind::Ref => {
-                    if kl.ident == "" {
+                    if kl.ident.is_empty() {
                         kl.ident = format!("kref-{}", db.backrefs.len());
                     }
-                    let attrs = (kl.ident.clone(), vec!["kl-ref".to_string(), "kl-defined".to_string()], vec![]);
-                    let title = format!("Reference to {}", entry.synonyms[0].to_string());
-                    let target : (String, String) = (format!("#kl-{}", kid.0), title);
+                    let mut classes = vec!["kl-ref".to_string(), "kl-defined".to_string()];
+                    let mut keyvals = vec![];
+                    entry.attributes(&mut classes, &mut keyvals);
+                    let title = match &entry.description {
+                        Some(description) => description.clone(),
+                        None => format!("Reference to {}", entry.display_name()),
+                    };
+                    // an external page takes precedence over the introduction
+                    let url = match (entry.url(), db.intros.get(&kid)) {
+                        (Some(url), _) => {
+                            classes.push("kl-url".to_string());
+                            url
+                        }
+                        (None, Some(position)) => {
+                            if kl.location.position < *position {
+                                classes.push("kl-forward".to_string());
+                            }
+                            format!("#{}", kl_unique_id)
+                        }
+                        // declared but never introduced: there is nothing to link to
+                        (None, None) => {
+                            classes.push("kl-declared".to_string());
+                            keyvals.push(("title".to_string(), title));
+                            db.backrefs.push((kid, kl.clone()));
+                            return Inline::Span((kl.ident.clone(), classes, keyvals), content);
+                        }
+                    };
                     db.backrefs.push((kid, kl.clone()));
-                    Inline::Link(attrs, kl.content, target)
+                    Inline::Link((kl.ident.clone(), classes, keyvals), content, (url, title))
                 }
             }
         }
@@ -371,20 +1193,46 @@ fn parse_knowledge_synonym(meta : &MetaValue) -> Option<KnowledgeSynonym> {
 
 
 ///
-/// Parses a knowledge entry inside the metadata of a pandoc document.
-/// For now, a knowledge entry is simply a list of synonyms. But in the future,
-/// we may add more information (url, description, bibentry, etc.)
+/// Parses a knowledge entry inside the metadata of a pandoc document,
+/// following knowledge.schema.json. Only the synonyms are mandatory.
 ///
 fn parse_knowledge_entry(meta : &MetaValue) -> Option<KnowledgeEntry> {
     if let MetaValue::MetaMap(m) = meta {
-        let synonyms = m.get("synonyms")?;
-        match &**synonyms {
-            MetaValue::MetaList(l) => {
-                let synonyms = l.into_iter().filter_map(|e| parse_knowledge_synonym(&e) ).collect();
-                Some(KnowledgeEntry { synonyms })
-            }
-            _ => None
-        }
+        let synonyms = match &**m.get("synonyms")? {
+            MetaValue::MetaList(l) => l.iter().filter_map(parse_knowledge_synonym).collect(),
+            _ => return None
+        };
+        let text = |key : &str| m.get(key).and_then(|v| utils::meta_to_inline(v)).map(|i| utils::stringify_inlines(&i));
+        let notion = m.get("notion").and_then(|v| utils::meta_to_bool(v)).unwrap_or(true);
+        let case_sensitive = m.get("case-sensitive").and_then(|v| utils::meta_to_bool(v)).unwrap_or(true);
+        let urls = match m.get("url").map(|v| &**v) {
+            Some(MetaValue::MetaList(l)) => l.iter().filter_map(utils::meta_to_inline).map(|i| utils::stringify_inlines(&i)).collect(),
+            _ => text("url").into_iter().collect(),
+        };
+        let display = match m.get("display").map(|v| &**v) {
+            Some(MetaValue::MetaList(l)) => l.iter().filter_map(|d| {
+                let d = utils::stringify_inlines(&utils::meta_to_inline(d)?);
+                let display = KnowledgeDisplay::try_from(d.as_str()).ok();
+                if display.is_none() {
+                    eprintln!("[lipics] unknown knowledge display option `{}`", d);
+                }
+                display
+            }).collect(),
+            _ => vec![],
+        };
+        Some(KnowledgeEntry {
+            synonyms,
+            notion,
+            name: text("name"),
+            wikidata: text("wikidata"),
+            description: text("description"),
+            urls,
+            index: text("index"),
+            color: text("color"),
+            display,
+            placeholder: text("placeholder-text"),
+            case_sensitive,
+        })
     } else {
         None
     }
@@ -395,26 +1243,1149 @@ fn parse_knowledge_entry(meta : &MetaValue) -> Option<KnowledgeEntry> {
 ///
 fn parse_knowledge_entries(meta : &MetaValue) -> Option<Vec<KnowledgeEntry>> {
     if let MetaValue::MetaList(l) = meta {
-        Some(l.into_iter().filter_map(|e| parse_knowledge_entry(&e)).collect())
+        Some(l.iter().filter_map(parse_knowledge_entry).collect())
     } else {
         None
     }
 }
 
+///
+/// Parses the content of a knowledge file. It is either
+/// a list of knowledge entries, a single knowledge entry,
+/// or a map with a `knowledges` key (like a metadata block).
+/// JSON files are read as yaml.
+///
+fn parse_knowledge_file(path : &str) -> Option<Vec<KnowledgeEntry>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("[lipics] could not read the knowledge file {}: {}", path, e);
+            return None;
+        }
+    };
+    let value : serde_yaml::Value = match serde_yaml::from_str(&content) {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("[lipics] could not parse the knowledge file {}: {}", path, e);
+            return None;
+        }
+    };
+    let meta = utils::yaml_to_meta(&value)?;
+    let entries = match &meta {
+        MetaValue::MetaMap(m) if m.contains_key("knowledges") => parse_knowledge_entries(&m["knowledges"]),
+        MetaValue::MetaMap(_) => parse_knowledge_entry(&meta).map(|e| vec![e]),
+        _ => parse_knowledge_entries(&meta),
+    };
+    if entries.is_none() {
+        eprintln!("[lipics] the knowledge file {} does not contain knowledges", path);
+    }
+    entries
+}
+
+/// Lists the files of the `knowledge-files` metadata
+fn knowledge_files(meta : &BTreeMap<String, MetaValue>) -> Vec<String> {
+    let to_path = |m: &MetaValue| utils::meta_to_inline(m).map(|i| utils::stringify_inlines(&i));
+    match meta.get("knowledge-files") {
+        Some(MetaValue::MetaList(l)) => l.iter().filter_map(to_path).collect(),
+        Some(m) => to_path(m).into_iter().collect(),
+        None => vec![],
+    }
+}
+
+impl KnowledgeBase {
+    /// Adds the entries coming from `source` to the knowledge base.
+    /// A synonym that already names another knowledge is a conflict:
+    /// the first definition wins, the synonym is removed from the
+    /// later entry, and entries whose synonyms are all taken are dropped.
+    /// `sources` records the source and the name of the entry of each synonym.
+    fn merge(&mut self, entries : Vec<KnowledgeEntry>, source : &str, sources : &mut HashMap<KnowledgeKey, (String, String)>) {
+        for mut entry in entries {
+            let id = KnowledgeId(self.forward.len());
+            let name = entry.display_name();
+            let mut synonyms = vec![];
+            let mut keys = vec![];
+            for syn in entry.synonyms.drain(..) {
+                let key = syn.key(!entry.case_sensitive);
+                // the same synonym twice in one entry
+                if keys.contains(&key) {
+                    continue;
+                }
+                if let Some((previous, other)) = sources.get(&key) {
+                    eprintln!("[lipics] knowledge conflict: `{}` of `{}` from {} is already a synonym of `{}` from {}",
+                              syn, name, source, other, previous);
+                    continue;
+                }
+                keys.push(key);
+                synonyms.push(syn);
+            }
+            if synonyms.is_empty() {
+                continue;
+            }
+            entry.synonyms = synonyms;
+            let name = entry.display_name();
+            for key in keys {
+                sources.insert(key.clone(), (source.to_string(), name.clone()));
+                self.canonize.insert(key, id);
+            }
+            self.forward.push(entry);
+        }
+    }
+}
+
+impl KnowledgeBase {
+    pub fn is_empty(&self) -> bool {
+        self.forward.is_empty()
+    }
+
+    /// The anchor of the introduction of a knowledge
+    pub fn anchor(&self, kid: KnowledgeId) -> &str {
+        &self.anchors[kid.0]
+    }
+
+    /// Puts every notion in the LaTeX index,
+    /// under its name when it has no `index` field
+    pub fn index_notions(&mut self) {
+        for entry in self.forward.iter_mut().filter(|e| e.notion && e.index.is_none()) {
+            entry.index = Some(entry.display_name());
+        }
+    }
+
+    /// Loads the knowledge package with `options`
+    /// and declares every knowledge of the base
+    pub fn to_latex(&self, options: &[&str]) -> String {
+        let mut latex = format!("\\usepackage[{}]{{knowledge}}\n\\knowledgeconfigure{{notion}}\n", options.join(","));
+        for entry in &self.forward {
+            latex.push_str(&entry.to_latex());
+        }
+        latex
+    }
+}
+
+/// A knowledge reference inside of a formula, for the
+/// knowledge package: \kl(scope)[name]{text} as outside of formulas
+pub fn math_knowledge_to_latex(db: &mut KnowledgeResolver, mut kl: KnowledgeCommand) -> String {
+    let text = utils::stringify_inlines(&kl.content);
+    match resolve_inflected(&db.knowledge, &db.inflections, &mut kl) {
+        None => db.unknown.push(kl.clone()),
+        Some((kid, _)) => db.backrefs.push((kid, kl.clone())),
+    }
+    format!("\\kl{}{{{}}}", latex_parameters(&kl, &text), text)
+}
+
+/// A knowledge reference inside of a formula,
+/// in the fast LaTeX mode: \aklref{unique-id}{text},
+/// or \href, \akldeclared and \aklreferror as outside of formulas.
+pub fn math_knowledge_to_fast_latex(db: &mut KnowledgeResolver, mut kl: KnowledgeCommand) -> String {
+    let text = utils::stringify_inlines(&kl.content);
+    match resolve_inflected(&db.knowledge, &db.inflections, &mut kl) {
+        None => {
+            db.unknown.push(kl);
+            format!("\\aklreferror{{{}}}", text)
+        }
+        Some((kid, entry)) => {
+            let kl_unique_id = db.knowledge.anchor(kid).to_string();
+            let url = entry.url();
+            db.backrefs.push((kid, kl));
+            match url {
+                Some(url) => format!("\\href{{{}}}{{{}}}", url, text),
+                None if !db.intros.contains_key(&kid) => format!("\\akldeclared{{{}}}{{{}}}", kl_unique_id, text),
+                None => format!("\\aklref{{{}}}{{{}}}", kl_unique_id, text),
+            }
+        }
+    }
+}
+
+/// A knowledge reference inside of a formula, for pandoc:
+/// a link that MathJax understands when `mathjax` is set
+/// (\href{#kl-name}{text}), and the bare text otherwise.
+pub fn math_knowledge_to_pandoc(db: &mut KnowledgeResolver, mut kl: KnowledgeCommand, mathjax: bool) -> String {
+    let text = utils::stringify_inlines(&kl.content);
+    let target = match resolve_inflected(&db.knowledge, &db.inflections, &mut kl) {
+        None => {
+            db.unknown.push(kl);
+            None
+        }
+        Some((kid, entry)) => {
+            let target = match (entry.url(), db.intros.contains_key(&kid)) {
+                (Some(url), _) => Some(url),
+                (None, true) => Some(format!("#{}", db.knowledge.anchor(kid))),
+                (None, false) => None,
+            };
+            db.backrefs.push((kid, kl));
+            target
+        }
+    };
+    match target {
+        Some(target) if mathjax => format!("\\href{{{}}}{{{}}}", target, text),
+        _ => format!("{{{}}}", text),
+    }
+}
+
 /// Parses a knowledge base from 
-/// the metadata of a pandoc document.
+/// the metadata of a pandoc document,
+/// merging the inline `knowledges` with
+/// the ones of the `knowledge-files`.
 pub fn parse_knowledge_base(meta : &BTreeMap<String, MetaValue>) -> KnowledgeBase {
-    let entries = meta.get("knowledges").and_then(|x| parse_knowledge_entries(&*x));
-    if let Some(forward) = entries {
-        let mut canonize = HashMap::new();
-        for (i,entry) in forward.iter().enumerate() {
-            let id = KnowledgeId(i as u16);
-            for syn in &entry.synonyms {
-                canonize.insert(syn.clone(), id);
-            }
+    let mut kb = KnowledgeBase { forward: vec![], canonize: HashMap::new(), anchors: vec![] };
+    let mut sources = HashMap::new();
+    if let Some(entries) = meta.get("knowledges").and_then(parse_knowledge_entries) {
+        kb.merge(entries, "the metadata", &mut sources);
+    }
+    for file in knowledge_files(meta) {
+        if let Some(entries) = parse_knowledge_file(&file) {
+            kb.merge(entries, &file, &mut sources);
         }
-        KnowledgeBase { forward, canonize }
+    }
+    kb.anchors = knowledge_anchors(&kb.forward);
+    kb
+}
+
+/// The anchor of a knowledge named `name`
+/// automaton@Büchi -> kl-automaton-büchi
+fn anchor_slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "kl-knowledge".to_string()
     } else {
-        KnowledgeBase { forward: vec![], canonize: HashMap::new() }
+        format!("kl-{}", slug)
+    }
+}
+
+/// The anchors of the knowledges, derived from their
+/// names so that links survive the reordering of the
+/// knowledges: homonyms get a numbered suffix.
+fn knowledge_anchors(entries: &[KnowledgeEntry]) -> Vec<String> {
+    let mut used = HashSet::new();
+    entries.iter().map(|entry| {
+        let slug = anchor_slug(&entry.display_name());
+        let mut anchor = slug.clone();
+        let mut n = 1;
+        while used.contains(&anchor) {
+            n += 1;
+            anchor = format!("{}-{}", slug, n);
+        }
+        used.insert(anchor.clone());
+        anchor
+    }).collect()
+}
+
+/// Wraps the occurrences of synonyms in plain text
+/// into references `[synonym]{.ref}`, preferring the
+/// longest synonym. Headings, math, code, links and
+/// existing knowledge commands are left untouched.
+#[derive(Debug)]
+pub struct AutoLinker {
+    canonize: HashMap<KnowledgeKey, KnowledgeId>,
+    /// The number of words of the longest synonym
+    longest: usize,
+    /// Only link the first occurrence of a knowledge in a paragraph
+    first_only: bool,
+    /// The knowledges linked in the current paragraph
+    linked: HashSet<KnowledgeId>,
+}
+
+/// Punctuation that may surround a word without being part of it
+fn is_punctuation(c: char) -> bool {
+    matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | '(' | ')' | '[' | ']' | '"' | '\'' | '“' | '”' | '‘' | '’')
+}
+
+impl KnowledgeBase {
+    /// An autolinker for the global synonyms of the knowledge base
+    pub fn autolinker(&self, first_only: bool) -> AutoLinker {
+        let canonize : HashMap<KnowledgeKey, KnowledgeId> = self.canonize.iter()
+            .filter(|(key, _)| key.scope.is_none())
+            .map(|(key, id)| (key.clone(), *id))
+            .collect();
+        let longest = canonize.keys().map(|key| key.text.split(' ').count()).max().unwrap_or(0);
+        AutoLinker { canonize, longest, first_only, linked: HashSet::new() }
+    }
+}
+
+impl AutoLinker {
+    fn lookup(&self, text: &str) -> Option<KnowledgeId> {
+        let synonym = KnowledgeSynonym::Global(vec![Inline::Str(text.to_string())]);
+        self.canonize.get(&synonym.key(false)).or_else(|| self.canonize.get(&synonym.key(true))).copied()
+    }
+
+    /// Tries to link the longest synonym starting at `inlines[start]`.
+    /// Returns the new inlines and the number of inlines consumed.
+    fn link_at(&mut self, inlines: &[Inline], start: usize) -> Option<(Vec<Inline>, usize)> {
+        // the positions of the words following `start`
+        let mut words = vec![];
+        let mut i = start;
+        while words.len() < self.longest {
+            match inlines.get(i) {
+                Some(Inline::Str(_)) => words.push(i),
+                _ => break,
+            }
+            match inlines.get(i + 1) {
+                Some(Inline::Space) | Some(Inline::SoftBreak) => i += 2,
+                _ => break,
+            }
+        }
+        for n in (1..=words.len()).rev() {
+            let first = words[0];
+            let last = words[n - 1];
+            let text = utils::stringify_inlines(&inlines[first..=last]);
+            let lead : String = text.chars().take_while(|c| is_punctuation(*c)).collect();
+            let trimmed = text[lead.len()..].trim_end_matches(is_punctuation);
+            let trail = text[lead.len() + trimmed.len()..].to_string();
+            if trimmed.is_empty() || trimmed != trimmed.trim() {
+                continue;
+            }
+            let Some(id) = self.lookup(&trimmed.split_whitespace().collect::<Vec<_>>().join(" ")) else {
+                continue;
+            };
+            if self.first_only && !self.linked.insert(id) {
+                return None;
+            }
+            let mut content = inlines[first..=last].to_vec();
+            if let Some(Inline::Str(s)) = content.first_mut() {
+                *s = s[lead.len()..].to_string();
+            }
+            if let Some(Inline::Str(s)) = content.last_mut() {
+                *s = s[..s.len() - trail.len()].to_string();
+            }
+            let mut out = vec![];
+            if !lead.is_empty() {
+                out.push(Inline::Str(lead));
+            }
+            out.push(Inline::Span(("".to_string(), vec!["ref".to_string()], vec![]), content));
+            if !trail.is_empty() {
+                out.push(Inline::Str(trail));
+            }
+            return Some((out, last - first + 1));
+        }
+        None
+    }
+}
+
+impl MutVisitor for AutoLinker {
+    fn visit_block(&mut self, block: &mut Block) {
+        match block {
+            Block::Header(..) | Block::CodeBlock(..) | Block::RawBlock(..) => {}
+            Block::Para(_) | Block::Plain(_) => {
+                self.linked.clear();
+                self.walk_block(block);
+            }
+            _ => self.walk_block(block),
+        }
+    }
+
+    fn visit_inline(&mut self, inline: &mut Inline) {
+        match inline {
+            Inline::Link(..) | Inline::Code(..) | Inline::Math(..) | Inline::RawInline(..)
+                | Inline::Cite(..) | Inline::Image(..) => {}
+            Inline::Span((_, classes, _), _) if classes_to_knowledge_kind(classes).is_some() => {}
+            _ => self.walk_inline(inline),
+        }
+    }
+
+    fn visit_vec_inline(&mut self, inlines: &mut Vec<Inline>) {
+        let mut new_inlines = vec![];
+        let mut i = 0;
+        while i < inlines.len() {
+            match self.link_at(inlines, i) {
+                Some((linked, consumed)) => {
+                    new_inlines.extend(linked);
+                    i += consumed;
+                }
+                None => {
+                    new_inlines.push(inlines[i].clone());
+                    i += 1;
+                }
+            }
+        }
+        *inlines = new_inlines;
+        self.walk_vec_inline(inlines);
+    }
+}
+
+/// The identifier (or class) of the div replaced by the glossary
+pub const KNOWLEDGE_INDEX: &str = "knowledge-index";
+
+/// Whether a div is replaced by the glossary
+fn is_knowledge_index((id, classes, _): &pandoc_ast::Attr) -> bool {
+    id == KNOWLEDGE_INDEX || classes.iter().any(|c| c == KNOWLEDGE_INDEX)
+}
+
+/// Whether the document asks for a knowledge index
+pub fn has_knowledge_index(blocks: &[Block]) -> bool {
+    blocks.iter().any(|b| match b {
+        Block::Div(attr, inner) => is_knowledge_index(attr) || has_knowledge_index(inner),
+        _ => false,
+    })
+}
+
+/// Replaces the content of the `#knowledge-index` div
+pub fn fill_knowledge_index(blocks: &mut [Block], content: &[Block]) {
+    for b in blocks.iter_mut() {
+        if let Block::Div(attr, inner) = b {
+            if is_knowledge_index(attr) {
+                *inner = content.to_vec();
+            } else {
+                fill_knowledge_index(inner, content);
+            }
+        }
+    }
+}
+
+/// The section of a knowledge command, for the glossary
+fn section_name(kl: &KnowledgeCommand) -> String {
+    kl.location.section.clone().unwrap_or_else(|| "Preamble".to_string())
+}
+
+impl KnowledgeResolver {
+    /// A glossary of the notions, as a definition list:
+    /// the synonyms, the description, where the notion
+    /// is introduced and where it is used.
+    pub fn glossary(&self) -> Vec<Block> {
+        let mut entries : Vec<(KnowledgeId, &KnowledgeEntry)> = self.knowledge.forward.iter().enumerate()
+            .filter(|(_, e)| e.notion)
+            .map(|(i, e)| (KnowledgeId(i), e))
+            .collect();
+        entries.sort_by_key(|(_, e)| e.display_name().to_lowercase());
+
+        let words = |s: &str| -> Vec<Inline> {
+            let mut out = vec![];
+            for (i, w) in s.split_whitespace().enumerate() {
+                if i > 0 {
+                    out.push(Inline::Space);
+                }
+                out.push(Inline::Str(w.to_string()));
+            }
+            out
+        };
+        let link = |content: Vec<Inline>, target: String| {
+            Inline::Link(("".to_string(), vec![], vec![]), content, (target, "".to_string()))
+        };
+
+        let items = entries.into_iter().map(|(kid, entry)| {
+            let intro = self.introduced.iter().find(|(k, _)| *k == kid).map(|(_, kl)| kl);
+            let anchor = format!("#{}", self.knowledge.anchor(kid));
+
+            let term = match intro {
+                Some(_) => vec![link(words(&entry.display_name()), anchor.clone())],
+                None => words(&entry.display_name()),
+            };
+
+            let mut definition = vec![];
+            if entry.synonyms.len() > 1 {
+                let synonyms : Vec<String> = entry.synonyms.iter().map(|s| s.to_string()).collect();
+                let mut inlines = words("Synonyms:");
+                inlines.push(Inline::Space);
+                inlines.extend(words(&format!("{}.", synonyms.join(", "))));
+                definition.push(Block::Plain(inlines));
+            }
+            if let Some(description) = &entry.description {
+                definition.push(Block::Plain(words(description)));
+            }
+
+            let mut inlines = match intro {
+                Some(kl) => {
+                    let mut inlines = words("Introduced in");
+                    inlines.push(Inline::Space);
+                    inlines.push(link(words(&section_name(kl)), anchor));
+                    inlines.push(Inline::Str(".".to_string()));
+                    inlines
+                }
+                None => words("Never introduced."),
+            };
+
+            // the first reference of each section
+            let mut sections : Vec<(String, String)> = vec![];
+            for (_, kl) in self.backrefs.iter().filter(|(k, _)| *k == kid) {
+                let section = section_name(kl);
+                if !kl.ident.is_empty() && !sections.iter().any(|(s, _)| *s == section) {
+                    sections.push((section, format!("#{}", kl.ident)));
+                }
+            }
+            if !sections.is_empty() {
+                inlines.push(Inline::Space);
+                inlines.extend(words("Used in"));
+                for (i, (section, target)) in sections.into_iter().enumerate() {
+                    if i > 0 {
+                        inlines.push(Inline::Str(",".to_string()));
+                    }
+                    inlines.push(Inline::Space);
+                    inlines.push(link(words(&section), target));
+                }
+                inlines.push(Inline::Str(".".to_string()));
+            }
+            definition.push(Block::Plain(inlines));
+
+            (term, vec![definition])
+        }).collect::<Vec<_>>();
+
+        if items.is_empty() {
+            vec![]
+        } else {
+            vec![Block::DefinitionList(items)]
+        }
+    }
+}
+
+impl KnowledgeResolver {
+    /// The decorator adding the references of
+    /// each knowledge to its introduction
+    pub fn backref_decorator(&self) -> BackrefDecorator {
+        let mut backrefs : HashMap<String, Vec<(String, String)>> = HashMap::new();
+        for (kid, kl) in self.backrefs.iter().filter(|(_, kl)| !kl.ident.is_empty()) {
+            backrefs.entry(self.knowledge.anchor(*kid).to_string()).or_default().push((kl.ident.clone(), section_name(kl)));
+        }
+        BackrefDecorator { backrefs }
+    }
+}
+
+/// Adds to every introduction `kl-name` the list of its references,
+/// as a `backrefs` attribute and as a superscript list of links
+/// (like the knowledge package in electronic mode).
+#[derive(Debug)]
+pub struct BackrefDecorator {
+    /// The references (identifier, section) of each introduction
+    backrefs: HashMap<String, Vec<(String, String)>>,
+}
+
+impl MutVisitor for BackrefDecorator {
+    fn visit_inline(&mut self, inline: &mut Inline) {
+        if let Inline::Span((id, classes, keyvals), content) = inline {
+            if classes.iter().any(|c| c == "kl-intro") {
+                if let Some(refs) = self.backrefs.get(id.as_str()) {
+                    let ids : Vec<String> = refs.iter().map(|(r, _)| r.clone()).collect();
+                    keyvals.push(("backrefs".to_string(), ids.join(" ")));
+                    let mut links = vec![];
+                    for (i, (r, section)) in refs.iter().enumerate() {
+                        if i > 0 {
+                            links.push(Inline::Str(",".to_string()));
+                        }
+                        links.push(Inline::Link(
+                            ("".to_string(), vec!["kl-backref".to_string()], vec![]),
+                            vec![Inline::Str((i + 1).to_string())],
+                            (format!("#{}", r), format!("Used in {}", section)),
+                        ));
+                    }
+                    content.push(Inline::Span(
+                        ("".to_string(), vec!["kl-backrefs".to_string()], vec![]),
+                        vec![Inline::Superscript(links)],
+                    ));
+                }
+                return;
+            }
+        }
+        self.walk_inline(inline);
+    }
+}
+
+impl KnowledgeResolver {
+    /// The decorator adding to every reference
+    /// a preview of the knowledge: the sentence
+    /// introducing it, or its description.
+    pub fn preview_decorator(&self) -> PreviewDecorator {
+        let mut previews = HashMap::new();
+        for (i, entry) in self.knowledge.forward.iter().enumerate() {
+            let kid = KnowledgeId(i);
+            let intro = self.introduced.iter().find(|(k, _)| *k == kid).map(|(_, kl)| kl);
+            let preview = intro
+                .map(|kl| kl.location.sentence(&kl.text()))
+                .filter(|p| !p.is_empty())
+                .or_else(|| entry.description.clone());
+            if let Some(preview) = preview {
+                previews.insert(kid, preview);
+            }
+        }
+        let references = self.backrefs.iter()
+            .filter(|(_, kl)| !kl.ident.is_empty())
+            .filter_map(|(kid, kl)| Some((kl.ident.clone(), previews.get(kid)?.clone())))
+            .collect();
+        PreviewDecorator { references }
+    }
+}
+
+/// Adds a `preview` attribute to the references of knowledges,
+/// that the templates can show as a tooltip.
+#[derive(Debug)]
+pub struct PreviewDecorator {
+    /// The preview of each reference, by identifier
+    references: HashMap<String, String>,
+}
+
+impl MutVisitor for PreviewDecorator {
+    fn visit_inline(&mut self, inline: &mut Inline) {
+        if let Inline::Link((id, _, keyvals), _, _) = inline {
+            if let Some(preview) = self.references.get(id.as_str()) {
+                keyvals.push(("preview".to_string(), preview.clone()));
+            }
+        }
+        self.walk_inline(inline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn knowledge_base(yaml : &str) -> KnowledgeBase {
+        let value : serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+        let meta = BTreeMap::from([("knowledges".to_string(), utils::yaml_to_meta(&value).unwrap())]);
+        parse_knowledge_base(&meta)
+    }
+
+    fn span(classes : &[&str], keyvals : &[(&str, &str)], text : &str) -> KnowledgeCommand {
+        let classes = classes.iter().map(|c| c.to_string()).collect();
+        let keyvals = keyvals.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        span_to_knowledge(&Inline::Span(("".into(), classes, keyvals), vec![Inline::Str(text.into())])).unwrap()
+    }
+
+    /// One line per item of `report[key]`, listing its fields
+    fn report_lines(report : &MetaValue, key : &str) -> Vec<String> {
+        let items = match report {
+            MetaValue::MetaMap(m) => match &*m[key] {
+                MetaValue::MetaList(l) => l.clone(),
+                other => panic!("unexpected {:?}", other),
+            },
+            other => panic!("unexpected {:?}", other),
+        };
+        items.iter().map(|item| match item {
+            MetaValue::MetaMap(m) => m.iter().map(|(k, v)| match &**v {
+                MetaValue::MetaString(s) => format!("{}={}", k, s),
+                MetaValue::MetaInlines(i) => format!("{}={}", k, utils::stringify_inlines(i)),
+                other => panic!("unexpected {:?}", other),
+            }).collect::<Vec<_>>().join(" "),
+            other => panic!("unexpected {:?}", other),
+        }).collect()
+    }
+
+    #[test]
+    fn span_kinds() {
+        assert_eq!(span(&["intro"], &[], "automaton").kind.name(), "intro");
+        assert_eq!(span(&["reintro"], &[], "automaton").kind.name(), "reintro");
+        let kl = span(&["ref"], &[("kl", "automaton"), ("scope", "words")], "automata");
+        assert_eq!(kl.kind.name(), "ref");
+        assert_eq!(kl.name.as_deref(), Some("automaton"));
+        assert_eq!(kl.scope.as_deref(), Some("words"));
+        assert!(span_to_knowledge(&Inline::Span(("".into(), vec![], vec![]), vec![])).is_none());
+    }
+
+    #[test]
+    fn report() {
+        let mut db = KnowledgeResolver::new(knowledge_base("[{synonyms: [automaton]}]"));
+        knowledge_to_pandoc(&mut db, span(&["intro"], &[], "automaton"));
+        knowledge_to_pandoc(&mut db, span(&["ref"], &[("kl", "automaton")], "automata"));
+        knowledge_to_pandoc(&mut db, span(&["ref"], &[], "monoid"));
+        let report = db.report();
+        assert_eq!(report_lines(&report, "introduced"),
+                   vec!["id=kl-automaton kind=intro knowledge=automaton text=automaton"]);
+        assert_eq!(report_lines(&report, "references"),
+                   vec!["anchor=kref-0 id=kl-automaton kind=ref knowledge=automaton name=automaton text=automata"]);
+        assert_eq!(report_lines(&report, "unknown"), vec!["kind=ref text=monoid"]);
+    }
+
+    #[test]
+    fn inflected_references() {
+        let kb = knowledge_base("[{synonyms: [automaton]}, {synonyms: [matrix]}]");
+        let mut db = KnowledgeResolver::new(kb.clone());
+        knowledge_to_pandoc(&mut db, span(&["ref"], &[], "automata"));
+        knowledge_to_pandoc(&mut db, span(&["ref"], &[], "matrices"));
+        // an explicit name is never inflected
+        knowledge_to_pandoc(&mut db, span(&["ref"], &[("kl", "automata")], "automata"));
+        let report = db.report();
+        assert_eq!(report_lines(&report, "references"), vec![
+            "anchor=kref-0 id=kl-automaton inflection=plural -ata (automaton) kind=ref knowledge=automaton text=automata",
+            "anchor=kref-1 id=kl-matrix inflection=plural -ices (matrix) kind=ref knowledge=matrix text=matrices",
+        ]);
+        assert_eq!(report_lines(&report, "unknown"), vec!["kind=ref name=automata text=automata"]);
+
+        let mut db = KnowledgeResolver::with_inflections(kb, vec![]);
+        knowledge_to_pandoc(&mut db, span(&["ref"], &[], "automata"));
+        assert_eq!(report_lines(&db.report(), "unknown"), vec!["kind=ref text=automata"]);
+    }
+
+    fn words(text : &str) -> Vec<Inline> {
+        let mut inlines = vec![];
+        for word in text.split(' ') {
+            if !inlines.is_empty() {
+                inlines.push(Inline::Space);
+            }
+            inlines.push(Inline::Str(word.to_string()));
+        }
+        inlines
+    }
+
+    /// Writes the references as `[text]`
+    fn show(inlines : &[Inline]) -> String {
+        inlines.iter().map(|i| match i {
+            Inline::Span((_, classes, _), content) if classes == &["ref"] => format!("[{}]", utils::stringify_inlines(content)),
+            Inline::Emph(content) => format!("_{}_", show(content)),
+            i => utils::stringify_inlines(std::slice::from_ref(i)),
+        }).collect()
+    }
+
+    fn autolink(first_only : bool, blocks : &mut Vec<Block>) {
+        let kb = knowledge_base("[{synonyms: [automaton, finite automaton]}, {synonyms: [word], case-sensitive: false}, {synonyms: [{name: run, scope: ab}]}]");
+        kb.autolinker(first_only).walk_vec_block(blocks);
+    }
+
+    fn autolink_para(first_only : bool, text : &str) -> String {
+        let mut blocks = vec![Block::Para(words(text))];
+        autolink(first_only, &mut blocks);
+        show(&para(&blocks[0]))
+    }
+
+    fn para(block : &Block) -> Vec<Inline> {
+        match block {
+            Block::Para(inlines) => inlines.clone(),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn autolink_longest_synonym() {
+        assert_eq!(autolink_para(false, "a finite automaton reads a word"), "a [finite automaton] reads a [word]");
+        assert_eq!(autolink_para(false, "(automaton), word."), "([automaton]), [word].");
+        assert_eq!(autolink_para(false, "Automaton Word words"), "Automaton [Word] words");
+        // scoped synonyms are never linked
+        assert_eq!(autolink_para(false, "a run"), "a run");
+    }
+
+    #[test]
+    fn autolink_first_only() {
+        assert_eq!(autolink_para(true, "word automaton word"), "[word] [automaton] word");
+        assert_eq!(autolink_para(false, "word automaton word"), "[word] [automaton] [word]");
+        let mut blocks = vec![Block::Para(words("word word")), Block::Plain(words("word"))];
+        autolink(true, &mut blocks);
+        assert_eq!(blocks, vec![Block::Para(vec![Inline::Span(("".into(), vec!["ref".into()], vec![]), words("word")), Inline::Space, Inline::Str("word".into())]),
+                                Block::Plain(vec![Inline::Span(("".into(), vec!["ref".into()], vec![]), words("word"))])]);
+    }
+
+    #[test]
+    fn autolink_skips_marked_text() {
+        let mut blocks = vec![
+            Block::Header(2, ("".into(), vec![], vec![]), words("word")),
+            Block::Para(vec![
+                Inline::Link(("".into(), vec![], vec![]), words("word"), ("#".into(), "".into())),
+                Inline::Space,
+                Inline::Span(("".into(), vec!["intro".into()], vec![]), words("automaton")),
+                Inline::Space,
+                Inline::Emph(words("word")),
+            ]),
+        ];
+        let original = blocks.clone();
+        autolink(false, &mut blocks);
+        assert_eq!(blocks[0], original[0]);
+        let inlines = para(&blocks[1]);
+        assert_eq!(inlines[..4], para(&original[1])[..4]);
+        assert_eq!(show(&inlines[4..]), "_[word]_");
+    }
+
+    #[test]
+    fn backrefs() {
+        let mut db = KnowledgeResolver::new(knowledge_base("[{synonyms: [automaton]}, {synonyms: [word]}]"));
+        let mut intro = knowledge_to_pandoc(&mut db, span(&["intro"], &[], "automaton"));
+        let mut lonely = knowledge_to_pandoc(&mut db, span(&["intro"], &[], "word"));
+        knowledge_to_pandoc(&mut db, span(&["ref"], &[], "automaton"));
+        let mut located = span(&["ref"], &[], "automaton");
+        located.locate(KnowledgeLocation::new(3, Some("Results".into()), vec![], "an automaton", "automaton"));
+        knowledge_to_pandoc(&mut db, located);
+
+        let mut decorator = db.backref_decorator();
+        let before = lonely.clone();
+        decorator.visit_inline(&mut lonely);
+        assert_eq!(lonely, before);
+
+        decorator.visit_inline(&mut intro);
+        let Inline::Span((_, _, keyvals), content) = &intro else { panic!("unexpected {:?}", intro) };
+        assert!(keyvals.contains(&("backrefs".to_string(), "kref-0 kref-1".to_string())));
+        let link = |n : &str, target : &str, section : &str| Inline::Link(
+            ("".into(), vec!["kl-backref".into()], vec![]),
+            vec![Inline::Str(n.into())],
+            (target.into(), format!("Used in {}", section)),
+        );
+        assert_eq!(content.last(), Some(&Inline::Span(
+            ("".into(), vec!["kl-backrefs".into()], vec![]),
+            vec![Inline::Superscript(vec![link("1", "#kref-0", "Preamble"), Inline::Str(",".into()), link("2", "#kref-1", "Results")])],
+        )));
+    }
+
+    #[test]
+    fn sentences() {
+        let location = KnowledgeLocation::new(0, None, vec![], "We fix an alphabet. An automaton reads words! Is it finite?", "automaton");
+        assert_eq!(location.sentence("automaton"), "An automaton reads words!");
+        assert_eq!(location.sentence("finite"), "Is it finite?");
+        assert_eq!(location.sentence("monoid"), location.context);
+    }
+
+    #[test]
+    fn previews() {
+        let mut db = KnowledgeResolver::new(knowledge_base(
+            "[{synonyms: [automaton]}, {synonyms: [word], description: A finite sequence}, {synonyms: [letter]}]"));
+        let mut intro = span(&["intro"], &[], "automaton");
+        intro.locate(KnowledgeLocation::new(0, None, vec![], "We fix an alphabet. An automaton reads words.", "automaton"));
+        // the introduction of `word` has no text around it
+        let intros = vec![intro, span(&["intro"], &[], "word")];
+        for kl in &intros {
+            db.collect(kl.clone());
+        }
+        for kl in intros {
+            knowledge_to_pandoc(&mut db, kl);
+        }
+        let mut refs : Vec<Inline> = ["automaton", "word", "letter"].iter()
+            .map(|text| knowledge_to_pandoc(&mut db, span(&["ref"], &[], text)))
+            .collect();
+        db.preview_decorator().walk_vec_inline(&mut refs);
+        let previews : Vec<Option<&str>> = refs.iter().map(|r| match r {
+            Inline::Link((_, _, keyvals), _, _) | Inline::Span((_, _, keyvals), _) =>
+                keyvals.iter().find(|(k, _)| k == "preview").map(|(_, v)| v.as_str()),
+            other => panic!("unexpected {:?}", other),
+        }).collect();
+        assert_eq!(previews, vec![Some("An automaton reads words."), Some("A finite sequence"), None]);
+    }
+
+    #[test]
+    fn collect_pass() {
+        let mut db = KnowledgeResolver::new(knowledge_base("[{synonyms: [automaton]}, {synonyms: [word]}]"));
+        let mut intro = span(&["intro"], &[], "automata");
+        intro.locate(KnowledgeLocation::new(2, None, vec![], "", "automata"));
+        db.collect(intro);
+        db.collect(span(&["ref"], &[], "word"));
+        let at = |position : usize, text : &str| {
+            let mut kl = span(&["ref"], &[], text);
+            kl.locate(KnowledgeLocation::new(position, None, vec![], "", text));
+            kl
+        };
+        let classes = |inline : Inline| match inline {
+            Inline::Link((_, classes, _), _, (url, _)) => format!("{} -> {}", classes.join(" "), url),
+            Inline::Span((_, classes, _), _) => classes.join(" "),
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(classes(knowledge_to_pandoc(&mut db, at(1, "automaton"))), "kl-ref kl-defined kl-notion kl-forward -> #kl-automaton");
+        assert_eq!(classes(knowledge_to_pandoc(&mut db, at(3, "automaton"))), "kl-ref kl-defined kl-notion -> #kl-automaton");
+        assert_eq!(classes(knowledge_to_pandoc(&mut db, at(3, "word"))), "kl-ref kl-defined kl-notion kl-declared");
+        let raw = |inlines : Vec<Inline>| match &inlines[0] {
+            Inline::RawInline(_, s) => s.clone(),
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(raw(knowledge_to_fast_latex(&mut db, at(3, "automaton"))), "\\aklref{kl-automaton}{");
+        assert_eq!(raw(knowledge_to_fast_latex(&mut db, at(3, "word"))), "\\akldeclared{kl-word}{");
+    }
+
+    #[test]
+    fn latex_declarations() {
+        let kb = knowledge_base(r#"
+- synonyms: ["a|b", "x@y", {name: "50% rule", scope: "s_1"}]
+  url: "https://example.org/a%20b#c"
+  index: "R&D"
+  placeholder-text: "a_b"
+  color: "red!50"
+- synonyms: ["\\ell_{2} {}"]
+  notion: false
+"#);
+        assert_eq!(kb.to_latex(&["electronic"]), [
+            "\\usepackage[electronic]{knowledge}",
+            "\\knowledgeconfigure{notion}",
+            "\\knowledge{notion,url={https://example.org/a\\%20b\\#c},color={red!50},index={R\\&D},text={a\\_b}}",
+            " | a{|}b",
+            " | x{@}y",
+            " | 50\\% rule@s\\_1",
+            "\\knowledge{}",
+            " | \\textbackslash{}ell\\_\\{2\\} \\{\\}",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn anchor_slugs() {
+        assert_eq!(anchor_slug("automaton@Büchi"), "kl-automaton-büchi");
+        assert_eq!(anchor_slug("Ω-regular language"), "kl-ω-regular-language");
+        assert_eq!(anchor_slug("  run (of A)! "), "kl-run-of-a");
+        assert_eq!(anchor_slug("→"), "kl-knowledge");
+    }
+
+    #[test]
+    fn duplicate_anchor_slugs() {
+        let kb = knowledge_base("
+- {name: 'automaton@Büchi', synonyms: [Büchi automaton]}
+- {name: 'Automaton Büchi', synonyms: [automaton of Büchi]}
+- {name: automaton-büchi-2, synonyms: [another automaton]}
+- {synonyms: ['→']}
+- {synonyms: ['⇒']}
+");
+        assert_eq!(kb.anchors, vec![
+            "kl-automaton-büchi",
+            "kl-automaton-büchi-2",
+            "kl-automaton-büchi-2-2",
+            "kl-knowledge",
+            "kl-knowledge-2",
+        ]);
+    }
+
+    /// The parts of a formula: the code of the text,
+    /// and the scope, name and formula of the references
+    fn math_parts(math: &str) -> Option<Vec<String>> {
+        let parts = math_to_knowledges(math)?;
+        Some(parts.into_iter().map(|part| match part {
+            MathPart::Text(text) => text,
+            MathPart::Knowledge(source, kl) => {
+                let formula = match kl.content.as_slice() {
+                    [Inline::Math(_, formula)] => formula.clone(),
+                    _ => panic!("{} is not a formula", source),
+                };
+                format!("{:?} {:?} {}", kl.scope, kl.name, formula)
+            }
+        }).collect())
+    }
+
+    #[test]
+    fn math_knowledges() {
+        assert_eq!(math_parts("\\kl{\\mathsf{Reg}} \\subseteq X"), Some(vec![
+            "None None \\mathsf{Reg}".to_string(),
+            " \\subseteq X".to_string(),
+        ]));
+        assert_eq!(math_parts("f(\\kl(A)[run] {\\rho}, \\kl{x})"), Some(vec![
+            "f(".to_string(),
+            "Some(\"A\") Some(\"run\") \\rho".to_string(),
+            ", ".to_string(),
+            "None None x".to_string(),
+            ")".to_string(),
+        ]));
+    }
+
+    #[test]
+    fn math_without_knowledges() {
+        assert_eq!(math_parts("x \\in X"), None);
+        assert_eq!(math_parts("\\klass{x}"), None);
+        assert_eq!(math_parts("\\kl{x} + \\klass"), Some(vec!["None None x".to_string(), " + \\klass".to_string()]));
+    }
+
+    #[test]
+    fn inherited_scopes() {
+        let mut db = KnowledgeResolver::new(knowledge_base("
+- {name: run, synonyms: [run]}
+- {name: run of an automaton, synonyms: [{name: run, scope: automata}]}
+- {name: play, synonyms: [{name: run, scope: games}]}
+"));
+        let within = |scopes : &[&str], kl : &mut KnowledgeCommand| {
+            let scopes = scopes.iter().map(|s| s.to_string()).collect();
+            kl.locate(KnowledgeLocation::new(0, None, scopes, "", "run"));
+        };
+        let mut games = span(&["ref"], &[], "run");
+        within(&["games", "automata"], &mut games);
+        let mut explicit = span(&["ref"], &[("scope", "automata")], "run");
+        within(&["games"], &mut explicit);
+        let mut elsewhere = span(&["ref"], &[], "run");
+        within(&["graphs"], &mut elsewhere);
+        for kl in [games, explicit, elsewhere] {
+            knowledge_to_pandoc(&mut db, kl);
+        }
+        let knowledges : Vec<String> = report_lines(&db.report(), "references").iter()
+            .map(|line| line.split(' ').find_map(|field| field.strip_prefix("id=")).unwrap().to_string())
+            .collect();
+        assert_eq!(knowledges, vec!["kl-play", "kl-run-of-an-automaton", "kl-run"]);
+        let shadowed : Vec<String> = db.diagnostics(true).iter()
+            .filter(|d| matches!(d.kind, KnowledgeDiagnosticKind::Shadowed(_)))
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(shadowed.len(), 1);
+        assert!(shadowed[0].starts_with("ambiguous knowledge: `play` shadows the global `run`"), "{}", shadowed[0]);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut db = KnowledgeResolver::new(knowledge_base("
+- {synonyms: [automaton]}
+- {synonyms: [run]}
+- {synonyms: [play]}
+- {synonyms: [word]}
+- {synonyms: [letter]}
+- {synonyms: [alphabet], notion: false}
+"));
+        let at = |position : usize, mut kl : KnowledgeCommand| {
+            kl.locate(KnowledgeLocation::new(position, None, vec![], "", ""));
+            kl
+        };
+        let commands = [
+            at(0, span(&["intro"], &[], "automaton")),
+            at(1, span(&["ref"], &[], "automaton")),
+            at(2, span(&["intro"], &[], "automaton")),
+            at(3, span(&["ref"], &[], "run")),
+            at(4, span(&["intro"], &[], "run")),
+            at(5, span(&["intro"], &[], "play")),
+            at(6, span(&["ref"], &[], "word")),
+            at(7, span(&["ref"], &[], "automaon")),
+        ];
+        for kl in commands {
+            knowledge_to_pandoc(&mut db, kl);
+        }
+        let kinds = |unused_notions : bool| -> Vec<(String, String, bool)> {
+            db.diagnostics(unused_notions).iter()
+                .map(|d| (format!("{:?}", d.kind), d.knowledge.clone(), d.location.is_some()))
+                .collect()
+        };
+        let triple = |kind : &str, knowledge : &str, located : bool| (kind.to_string(), knowledge.to_string(), located);
+        assert_eq!(kinds(false), vec![
+            triple("Unknown([\"automaton\"])", "automaon", true),
+            triple("IntroducedTwice", "automaton", true),
+            triple("RefBeforeIntro", "run", true),
+            triple("NeverReferenced", "play", true),
+            triple("NeverIntroduced", "word", true),
+        ]);
+        let unused : Vec<String> = db.diagnostics(true).iter().skip(5).map(|d| d.to_string()).collect();
+        assert_eq!(unused, vec!["knowledge `letter` is declared but never introduced"]);
+        let word = db.diagnostics(true)[4].to_string();
+        assert!(word.starts_with("knowledge `word` is referenced but never introduced"), "{}", word);
+        let unknown = db.diagnostics(true)[0].to_string();
+        assert!(unknown.ends_with("(did you mean `automaton`?)"), "{}", unknown);
+    }
+
+    #[test]
+    fn strict_diagnostics() {
+        let mut db = KnowledgeResolver::new(knowledge_base("- {synonyms: [automaton]}"));
+        assert_eq!(check_diagnostics(&db.diagnostics(true), false), Ok(vec![
+            "knowledge `automaton` is declared but never introduced".to_string(),
+        ]));
+        assert_eq!(check_diagnostics(&db.diagnostics(true), true), Err(vec![
+            "error: knowledge `automaton` is declared but never introduced".to_string(),
+        ]));
+        assert_eq!(check_diagnostics(&db.diagnostics(false), true), Ok(vec![]));
+        knowledge_to_pandoc(&mut db, span(&["intro"], &[], "automaton"));
+        knowledge_to_pandoc(&mut db, span(&["ref"], &[], "automaton"));
+        assert_eq!(check_diagnostics(&db.diagnostics(true), true), Ok(vec![]));
+        assert_eq!(check_diagnostics(&db.diagnostics(true), false), Ok(vec![]));
+    }
+
+    #[test]
+    fn entry_fields_in_pandoc() {
+        let kb = || knowledge_base("
+- synonyms: [automaton]
+  display: [bold, uppercase]
+  color: red
+  index: automata
+- synonyms: [run]
+  url: https://example.org/run
+  placeholder-text: ρ
+");
+        let mut db = KnowledgeResolver::new(kb());
+        assert_eq!(knowledge_to_pandoc(&mut db, span(&["intro"], &[], "automaton")), Inline::Span(
+            ("kl-automaton".into(), vec!["kl-intro".into(), "kl-defined".into(), "kl-notion".into(), "kl-bold".into(), "kl-uppercase".into()],
+             vec![("kl".into(), "kl-automaton".into()), ("style".into(), "color: red".into())]),
+            vec![Inline::Emph(vec![Inline::Strong(vec![Inline::Str("AUTOMATON".into())])])],
+        ));
+        match knowledge_to_pandoc(&mut db, span(&["ref"], &[], "run")) {
+            Inline::Link((_, classes, _), content, (url, _)) => {
+                assert!(classes.contains(&"kl-url".to_string()), "{:?}", classes);
+                assert_eq!(content, vec![Inline::Str("ρ".into())]);
+                assert_eq!(url, "https://example.org/run");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // the index entry is only written for LaTeX
+        let mut db = KnowledgeResolver::new(kb()).with_latex_index(true);
+        match knowledge_to_pandoc(&mut db, span(&["intro"], &[], "automaton")) {
+            Inline::Span(_, inlines) => assert_eq!(inlines.last(), Some(&Inline::RawInline(
+                pandoc_ast::Format("latex".into()), "\\index{automata}".into()))),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn glossary() {
+        let mut db = KnowledgeResolver::new(knowledge_base("
+- {synonyms: [word, finite word], description: A sequence of letters}
+- {synonyms: [automaton]}
+- {synonyms: [alphabet], notion: false}
+"));
+        let mut at = |section : &str, mut kl : KnowledgeCommand| {
+            kl.locate(KnowledgeLocation::new(0, Some(section.to_string()), vec![], "", ""));
+            knowledge_to_pandoc(&mut db, kl);
+        };
+        at("Words", span(&["intro"], &[], "word"));
+        at("Automata", span(&["ref"], &[], "word"));
+        at("Automata", span(&["ref"], &[], "finite word"));
+        at("Automata", span(&["ref"], &[], "alphabet"));
+        let mut blocks = vec![
+            Block::Para(vec![Inline::Str("text".into())]),
+            Block::Div(("".into(), vec!["knowledge-index".into()], vec![]), vec![]),
+        ];
+        assert!(has_knowledge_index(&blocks));
+        fill_knowledge_index(&mut blocks, &db.glossary());
+        let items = match &blocks[1] {
+            Block::Div(_, inner) => match &inner[..] {
+                [Block::DefinitionList(items)] => items.clone(),
+                other => panic!("unexpected {:?}", other),
+            },
+            other => panic!("unexpected {:?}", other),
+        };
+        let lines : Vec<String> = items.iter().map(|(term, definitions)| {
+            let mut line = utils::stringify_inlines(term);
+            for block in &definitions[0] {
+                if let Block::Plain(inlines) = block {
+                    line.push_str(&format!(" / {}", utils::stringify_inlines(inlines)));
+                }
+            }
+            line
+        }).collect();
+        assert_eq!(lines, vec![
+            "automaton / Never introduced.",
+            "word / Synonyms: word, finite word. / A sequence of letters / Introduced in Words. Used in Automata.",
+        ]);
+        assert!(!has_knowledge_index(&[Block::Div(("index".into(), vec![], vec![]), vec![])]));
+    }
+
+    #[test]
+    fn smart_quotes() {
+        let mut db = KnowledgeResolver::new(knowledge_base("- {synonyms: [automaton, Büchi automaton]}"));
+        let latex = |db: &mut KnowledgeResolver, text: &str| match knowledge_to_latex(db, span(&["ref"], &[], text)).first() {
+            Some(Inline::RawInline(_, command)) => command.clone(),
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(latex(&mut db, "automaton’s"), "\\kl[automaton]{");
+        assert_eq!(latex(&mut db, "Büchi automaton’s"), "\\kl[Büchi automaton]{");
+        assert_eq!(latex(&mut db, "automaton"), "\\kl{");
+        let inflections: Vec<Option<&str>> = db.backrefs.iter().map(|(_, kl)| kl.inflection.as_deref()).collect();
+        assert_eq!(inflections, vec![Some("possessive ’s"), Some("possessive ’s"), None]);
+        assert!(db.unknown.is_empty());
+    }
+
+    #[test]
+    fn merge_conflicts() {
+        let kb = knowledge_base("
+- {synonyms: [automaton, machine]}
+- {name: transducer, synonyms: [transducer, machine, transducer]}
+- {synonyms: [Run], case-sensitive: false}
+- {synonyms: [RUN], case-sensitive: false}
+- {synonyms: [Automaton]}
+");
+        let names : Vec<String> = kb.forward.iter().map(|e| e.display_name()).collect();
+        assert_eq!(names, vec!["automaton", "transducer", "Run", "Automaton"]);
+        let synonyms : Vec<String> = kb.forward[1].synonyms.iter().map(|s| s.to_string()).collect();
+        assert_eq!(synonyms, vec!["transducer"]);
+        assert_eq!(kb.to_latex(&[]).matches(" | machine\n").count(), 1);
+    }
+
+    #[test]
+    fn merge_sources() {
+        let entries = |yaml : &str| {
+            let value : serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+            parse_knowledge_entries(&utils::yaml_to_meta(&value).unwrap()).unwrap()
+        };
+        let mut kb = KnowledgeBase { forward: vec![], canonize: HashMap::new(), anchors: vec![] };
+        let mut sources = HashMap::new();
+        kb.merge(entries("[{synonyms: [automaton]}]"), "the metadata", &mut sources);
+        kb.merge(entries("[{synonyms: [automaton, run]}, {synonyms: [automaton]}]"), "automata.yaml", &mut sources);
+        assert_eq!(kb.forward.len(), 2);
+        let automaton = KnowledgeSynonym::Global(vec![Inline::Str("automaton".into())]).key(false);
+        let run = KnowledgeSynonym::Global(vec![Inline::Str("run".into())]).key(false);
+        assert_eq!(sources[&automaton], ("the metadata".to_string(), "automaton".to_string()));
+        assert_eq!(sources[&run], ("automata.yaml".to_string(), "run".to_string()));
+        assert_eq!(kb.canonize[&automaton], KnowledgeId(0));
+        assert_eq!(kb.canonize[&run], KnowledgeId(1));
     }
 }
@@ -1,4 +1,6 @@
 pub mod citations;
+pub mod inflections;
+pub mod knowledge_import;
 pub mod knowledges;
 pub mod polyreg;
 pub mod references;
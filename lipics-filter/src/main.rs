@@ -1,10 +1,17 @@
-use pandoc_ast::{Inline, MutVisitor};
+use pandoc_ast::{Block, Inline, MutVisitor};
 use pandoc_ast::MetaValue;
 use std::collections::BTreeMap;
 use std::io::{self, Read, Write};
 
-use lipics_filter::knowledges::{KnowledgeResolver, span_to_knowledge, parse_knowledge_base,
-knowledge_to_latex, knowledge_to_fast_latex, knowledge_to_pandoc };
+use lipics_filter::knowledges::{attr_scope, check_diagnostics, has_knowledge_index, fill_knowledge_index, KnowledgeCommand, KnowledgeResolver, KnowledgeLocation, span_to_knowledge, parse_knowledge_base,
+knowledge_to_latex, knowledge_to_fast_latex, knowledge_to_pandoc,
+math_to_knowledges, math_knowledge_to_latex, math_knowledge_to_fast_latex, math_knowledge_to_pandoc, MathPart, FAST_LATEX_MACROS };
+use lipics_filter::theorems::{self, Theorem, block_to_theorem, theorem_to_latex, theorem_to_html, theorem_to_pandoc,
+appendix_to_latex, appendix_to_pandoc, parse_theorem_declarations, declarations_to_latex};
+use lipics_filter::inflections::parse_inflection_rules;
+use lipics_filter::knowledge_import::import_knowledges;
+use lipics_filter::citations::{bibliography, parse_citation_words, CitationRewriter};
+use lipics_filter::references::{AnchorCollector, CrefRewriter, IdCollector, Labels, ReferenceResolver};
 use lipics_filter::utils;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -14,28 +21,228 @@ enum OutputMode {
     Pandoc,
 }
 
+/// The family of the output format
+/// given by pandoc to the filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Latex,
+    Html,
+    Other,
+}
+
+impl OutputFormat {
+    fn from_pandoc(format: Option<&str>) -> OutputFormat {
+        match format {
+            Some("latex") | Some("beamer") => OutputFormat::Latex,
+            Some(f) if f.starts_with("html") => OutputFormat::Html,
+            _ => OutputFormat::Other,
+        }
+    }
+}
+
 struct MyVisitor {
     kdb: KnowledgeResolver,
     mode: OutputMode,
+    thms: theorems::Context,
+    format: OutputFormat,
+    /// The title of the current section
+    section: Option<String>,
+    /// The text of the current run of inlines
+    context: String,
+    /// The number of knowledge commands seen so far
+    position: usize,
+    /// The `kl-scope` of the enclosing sections, with their level
+    section_scopes: Vec<(i64, String)>,
+    /// The `kl-scope` of the enclosing divs
+    div_scopes: Vec<String>,
+    /// During the first pass, knowledges are only
+    /// collected and the document is left untouched
+    collecting: bool,
+}
+
+impl MyVisitor {
+    /// Visits the inner content of a theorem
+    /// before it is converted to its final form,
+    /// so that nested environments and knowledges
+    /// are handled as well.
+    fn visit_theorem(&mut self, thm: &mut Theorem) {
+        let scope = thm.keyvals.get("kl-scope").cloned();
+        if let Some(scope) = &scope {
+            self.div_scopes.push(scope.clone());
+        }
+        if let Some(title) = thm.title.as_mut() {
+            self.visit_vec_inline(title);
+        }
+        self.visit_vec_block(&mut thm.statement);
+        for proof in thm.proofs.iter_mut() {
+            if let Some(title) = proof.title.as_mut() {
+                self.visit_vec_inline(title);
+            }
+            self.visit_vec_block(&mut proof.body);
+        }
+        if scope.is_some() {
+            self.div_scopes.pop();
+        }
+    }
+
+    /// Runs the collect pass then the rewrite pass,
+    /// like the two runs of LaTeX: when rewriting,
+    /// every introduction of the document is known.
+    fn run(&mut self, pandoc: &mut pandoc_ast::Pandoc) {
+        self.collecting = true;
+        self.walk_pandoc(pandoc);
+        self.collecting = false;
+        self.position = 0;
+        self.section = None;
+        self.section_scopes.clear();
+        self.walk_pandoc(pandoc);
+    }
+
+    /// Where the knowledge command is in the document
+    fn locate(&mut self, inline: &Inline) -> Option<KnowledgeCommand> {
+        span_to_knowledge(inline).map(|knowledge| self.locate_command(knowledge))
+    }
+
+    fn locate_command(&mut self, mut knowledge: KnowledgeCommand) -> KnowledgeCommand {
+        let scopes = self.div_scopes.iter().rev()
+            .chain(self.section_scopes.iter().rev().map(|(_, scope)| scope))
+            .cloned().collect();
+        let location = KnowledgeLocation::new(self.position, self.section.clone(), scopes, &self.context, &knowledge.text());
+        knowledge.locate(location);
+        self.position += 1;
+        knowledge
+    }
+
+    /// A header starts a section, that ends the
+    /// scopes of the previous sections of the same level
+    fn enter_section(&mut self, block: &Block) {
+        if let Block::Header(level, (_, _, keyvals), title) = block {
+            self.section = Some(utils::stringify_inlines(title));
+            self.section_scopes.retain(|(l, _)| l < level);
+            if let Some(scope) = attr_scope(keyvals) {
+                self.section_scopes.push((*level, scope));
+            }
+        }
+    }
+
+    /// Resolves the `\kl` commands of a formula.
+    fn visit_math(&mut self, parts: Vec<MathPart>) -> String {
+        let mut math = String::new();
+        for part in parts {
+            match part {
+                MathPart::Text(text) => math.push_str(&text),
+                MathPart::Knowledge(source, knowledge) => {
+                    let knowledge = self.locate_command(*knowledge);
+                    if self.collecting {
+                        self.kdb.collect(knowledge);
+                        math.push_str(&source);
+                        continue;
+                    }
+                    match self.mode {
+                        OutputMode::Latex => math.push_str(&math_knowledge_to_latex(&mut self.kdb, knowledge)),
+                        OutputMode::FastLatex => math.push_str(&math_knowledge_to_fast_latex(&mut self.kdb, knowledge)),
+                        OutputMode::Pandoc => {
+                            let mathjax = self.format == OutputFormat::Html;
+                            math.push_str(&math_knowledge_to_pandoc(&mut self.kdb, knowledge, mathjax));
+                        }
+                    }
+                }
+            }
+        }
+        math
+    }
 }
 
 impl MutVisitor for MyVisitor {
+    fn visit_vec_block(&mut self, blocks: &mut Vec<Block>) {
+        if self.collecting {
+            for block in blocks.iter_mut() {
+                self.enter_section(block);
+                self.visit_block(block);
+            }
+            return;
+        }
+        let mut new_blocks = vec![];
+        for mut block in blocks.drain(..) {
+            if let Some(mut thm) = block_to_theorem(&mut self.thms, block.clone()) {
+                self.visit_theorem(&mut thm);
+                self.thms.send_proofs_to_appendix(&mut thm);
+                match self.format {
+                    OutputFormat::Latex => new_blocks.extend(theorem_to_latex(thm)),
+                    OutputFormat::Html  => new_blocks.extend(theorem_to_html(thm)),
+                    OutputFormat::Other => new_blocks.extend(theorem_to_pandoc(thm)),
+                }
+            } else {
+                self.enter_section(&block);
+                self.visit_block(&mut block);
+                new_blocks.push(block);
+            }
+        }
+        *blocks = new_blocks;
+    }
+
+    fn visit_block(&mut self, block: &mut Block) {
+        let theorem = self.collecting && self.thms.is_theorem(block);
+        let scope = match block {
+            // the title header of a theorem overrides its
+            // keyvals, as in the rewrite pass
+            Block::Div((_, _, keyvals), blocks) if theorem => match blocks.first() {
+                Some(Block::Header(_, (_, _, title), _)) => attr_scope(title).or_else(|| attr_scope(keyvals)),
+                _ => attr_scope(keyvals),
+            },
+            Block::Div((_, _, keyvals), _) => attr_scope(keyvals),
+            _ => None,
+        };
+        if let Some(scope) = &scope {
+            self.div_scopes.push(scope.clone());
+        }
+        match block {
+            // the headers of a theorem are its title and
+            // its proofs, as in the rewrite pass
+            Block::Div(_, blocks) if theorem => {
+                for block in blocks.iter_mut() {
+                    self.visit_block(block);
+                }
+            }
+            _ => self.walk_block(block),
+        }
+        if scope.is_some() {
+            self.div_scopes.pop();
+        }
+    }
+
     fn visit_inline(&mut self, inline: &mut Inline) {
+        if let Inline::Math(_, math) = inline {
+            if let Some(parts) = math_to_knowledges(math) {
+                *math = self.visit_math(parts);
+            }
+        }
         if self.mode == OutputMode::Pandoc {
-            if let Some(knowledge) = span_to_knowledge(inline) {
-                *inline = knowledge_to_pandoc(&mut self.kdb, knowledge);
+            if let Some(knowledge) = self.locate(inline) {
+                if self.collecting {
+                    self.kdb.collect(knowledge);
+                } else {
+                    *inline = knowledge_to_pandoc(&mut self.kdb, knowledge);
+                }
             }
         } 
         self.walk_inline(inline);
     }
 
     fn visit_vec_inline(&mut self, inlines: &mut Vec<Inline>) {
-        if self.mode != OutputMode::Pandoc {
+        let context = std::mem::replace(&mut self.context, utils::stringify_inlines(inlines));
+        if self.mode != OutputMode::Pandoc && self.collecting {
+            for inline in inlines.iter() {
+                if let Some(knowledge) = self.locate(inline) {
+                    self.kdb.collect(knowledge);
+                }
+            }
+        } else if self.mode != OutputMode::Pandoc {
             let mut new_inlines = vec![];
             for inline in inlines.iter_mut() {
-                if let Some(knowledge) = span_to_knowledge(inline) {
+                if let Some(knowledge) = self.locate(inline) {
                     if self.mode == OutputMode::Latex {
-                        new_inlines.extend(knowledge_to_latex(knowledge));
+                        new_inlines.extend(knowledge_to_latex(&mut self.kdb, knowledge));
                     } else if self.mode == OutputMode::FastLatex {
                         new_inlines.extend(knowledge_to_fast_latex(&mut self.kdb, knowledge));
                     }
@@ -46,54 +253,21 @@ impl MutVisitor for MyVisitor {
             *inlines = new_inlines;
         } 
         self.walk_vec_inline(inlines);
+        self.context = context;
     }
 }
 
-/* 
-impl MutVisitor for MyVisitor {
-    fn visit_vec_block(&mut self, blocks: &mut Vec<Block>) {
-        let mut new_blocks = vec![];
-        for block in blocks.iter_mut() {
-            if let Some(thm) = block_to_theorem(&mut self.ctx, block.clone()) {
-                new_blocks.extend(theorem_to_latex(thm));
-            } else {
-                new_blocks.push(block.clone());
-            }
-        }
-        *blocks = new_blocks;
-        self.walk_vec_block(blocks);
-    }
-
-    fn visit_vec_inline(&mut self, inlines: &mut Vec<Inline>) {
-        let mut new_inlines = vec![];
-        for inline in inlines.iter_mut() {
-            if let Some(knowledge) = span_to_knowledge(&mut self.ctx, inline.clone()) {
-                new_inlines.extend(knowledge_to_latex(knowledge));
-            } else {
-                new_inlines.push(inline.clone());
-            }
-        }
-        *inlines = new_inlines;
-        self.walk_vec_inline(inlines);
-    }
-}
-*/
-
 #[derive(Debug)]
 struct PandocLipics {
     mode:  Option<OutputMode>,
     debug: bool,
-}
-
-#[derive(Debug)]
-struct Cli {
-    format: Option<String>,
+    /// Diagnostics are errors, whatever the mode
+    strict: bool,
 }
 
 fn parse_pandoc_lipics(meta: &BTreeMap<String, MetaValue>) -> PandocLipics {
     let mode = {
         let mode_m : Option<String> = utils::meta_deep_get(meta, "lipics.mode").and_then(|x| utils::meta_to_string(&x));
-        eprintln!("{:?}", mode_m);
         if let Some(s) = mode_m {
             if s == "latex" {
                 Some(OutputMode::Latex)
@@ -110,43 +284,274 @@ fn parse_pandoc_lipics(meta: &BTreeMap<String, MetaValue>) -> PandocLipics {
     }; 
 
     let debug = utils::meta_deep_get(meta, "lipics.debug").map(|_| true).unwrap_or(false);
+    let strict = utils::meta_deep_get(meta, "lipics.strict").and_then(|x| utils::meta_to_bool(&x)).unwrap_or(false);
 
     PandocLipics {
-        mode, debug
+        mode, debug, strict
     }
 }
 
+/// The options of the knowledge package,
+/// following the mode of the LIPIcs document
+fn knowledge_package_options(meta: &BTreeMap<String, MetaValue>) -> Vec<&'static str> {
+    let enabled = |key: &str| utils::meta_deep_get(meta, key).is_some_and(|x| utils::meta_to_bool(&x) != Some(false));
+    let mut options = vec!["cleveref", "xcolor", "hyperref"];
+    if enabled("lipics.review-mode") || enabled("lipics.final-mode") {
+        options.push("paper");
+    }
+    if enabled("lipics.arxiv-mode") {
+        options.push("electronic");
+    }
+    if enabled("lipics.composition-mode") {
+        options.push("composition");
+    }
+    options
+}
+
+/// lipics-filter import-knowledges [--json] [knowledges.tex]
+///
+/// Prints the knowledges declared in a LaTeX file
+/// (or the standard input) as yaml, or json with `--json`.
+fn import_knowledges_command(args: &[String]) {
+    let json = args.iter().any(|a| a == "--json");
+    let path = args.iter().find(|a| *a != "--json");
+    let mut input = String::new();
+    let read = match path {
+        Some(path) => std::fs::read_to_string(path).map(|s| input = s),
+        None => io::stdin().read_to_string(&mut input).map(|_| ()),
+    };
+    if let Err(e) = read {
+        eprintln!("[lipics] cannot read {}: {}", path.map_or("the standard input", |p| p.as_str()), e);
+        std::process::exit(1);
+    }
+    let (entries, errors) = import_knowledges(&input);
+    for error in &errors {
+        eprintln!("[lipics] {}: {}", path.map_or("<stdin>", |p| p.as_str()), error);
+    }
+    let entries = serde_yaml::Value::Sequence(entries);
+    let output = if json {
+        serde_json::to_string_pretty(&entries).map_err(|e| e.to_string())
+    } else {
+        serde_yaml::to_string(&entries).map_err(|e| e.to_string())
+    };
+    match output {
+        Ok(output) => println!("{}", output.trim_end()),
+        Err(e) => eprintln!("[lipics] cannot write the knowledges: {}", e),
+    }
+}
 
 fn main() {
     let mut s = String::new();
     let format = std::env::args().nth(1);
+    if format.as_deref() == Some("import-knowledges") {
+        import_knowledges_command(&std::env::args().skip(2).collect::<Vec<_>>());
+        return;
+    }
 
     io::stdin().read_to_string(&mut s).unwrap();
     let s = pandoc_ast::filter(s, |mut pandoc| {
-        let db  = parse_knowledge_base(&pandoc.meta);
-        let kdb = KnowledgeResolver::new(db);
+        let mut db = parse_knowledge_base(&pandoc.meta);
         let pandoc_lipics = parse_pandoc_lipics(&pandoc.meta);
+        let declared = parse_theorem_declarations(&pandoc.meta);
+        let output = OutputFormat::from_pandoc(format.as_deref());
 
         // Sane defaults
         // -> if the person did not ask for knowledge explicitly
         // we do not use it.
-        eprintln!("{:?}", pandoc_lipics);
-        eprintln!("{:?}", format);
-        let mode = if let Some(f) = format.as_deref() {
-            match f {
-                "latex" => pandoc_lipics.mode.unwrap_or(OutputMode::Pandoc),
-                _ => OutputMode::Pandoc,
-            }
+        if pandoc_lipics.debug {
+            eprintln!("{:?}", pandoc_lipics);
+            eprintln!("{:?}", format);
+        }
+        let mode = if output == OutputFormat::Latex {
+            pandoc_lipics.mode.unwrap_or(OutputMode::Pandoc)
         } else {
             OutputMode::Pandoc
         };
 
-        eprintln!("Mode: {:?}", mode);
+        if pandoc_lipics.debug {
+            eprintln!("Mode: {:?}", mode);
+        }
+
+        // In LaTeX, the knowledge index is the index of the document
+        let knowledge_index = has_knowledge_index(&pandoc.blocks);
+        if knowledge_index {
+            db.index_notions();
+        }
+        if knowledge_index && output == OutputFormat::Latex {
+            utils::meta_append(&mut pandoc.meta, "header-includes",
+                MetaValue::MetaBlocks(vec![Block::RawBlock(pandoc_ast::Format("latex".to_string()),
+                    "\\usepackage{imakeidx}\n\\makeindex".to_string())]));
+        }
+
+        // The knowledge package declares the merged knowledges
+        // with the options computed here
+        if mode == OutputMode::Latex && !db.is_empty() {
+            let declarations = db.to_latex(&knowledge_package_options(&pandoc.meta));
+            utils::meta_append(&mut pandoc.meta, "header-includes",
+                MetaValue::MetaBlocks(vec![Block::RawBlock(pandoc_ast::Format("latex".to_string()), declarations)]));
+        }
+        // The macros of the fast mode have default definitions
+        if mode == OutputMode::FastLatex {
+            utils::meta_append(&mut pandoc.meta, "header-includes",
+                MetaValue::MetaBlocks(vec![Block::RawBlock(pandoc_ast::Format("latex".to_string()), FAST_LATEX_MACROS.to_string())]));
+        }
+        // Unmarked synonyms become references
+        let autolink = utils::meta_deep_get(&pandoc.meta, "lipics.knowledge.autolink").and_then(|x| utils::meta_to_bool(&x));
+        if autolink == Some(true) {
+            let first_only = utils::meta_deep_get(&pandoc.meta, "lipics.knowledge.autolink-first")
+                .and_then(|x| utils::meta_to_bool(&x)).unwrap_or(false);
+            db.autolinker(first_only).walk_vec_block(&mut pandoc.blocks);
+        }
+        let kdb = KnowledgeResolver::with_inflections(db, parse_inflection_rules(&pandoc.meta))
+            .with_latex_index(output == OutputFormat::Latex);
         
-        let mut visitor = MyVisitor {  kdb, mode };
-        visitor.walk_pandoc(&mut pandoc);
-        eprintln!("{:?}", visitor.kdb);
+        if output == OutputFormat::Latex && !declared.is_empty() {
+            utils::meta_append(&mut pandoc.meta, "header-includes",
+                MetaValue::MetaBlocks(vec![declarations_to_latex(&declared)]));
+        }
+
+        // Labels are told apart from bibliography keys
+        // using the identifiers of the input document
+        let mut ids = IdCollector::default();
+        ids.walk_pandoc(&mut pandoc);
+        let bib = bibliography(&pandoc.meta);
+        let mut thms = theorems::Context::with_declarations(declared);
+        thms.reserve_ids(ids.ids.iter().cloned());
+        let labels = Labels::new(ids.ids, bib.keys().cloned().collect());
+
+        let mut visitor = MyVisitor {
+            kdb, mode, thms, format: output,
+            section: None, context: String::new(), position: 0,
+            section_scopes: vec![], div_scopes: vec![], collecting: false,
+        };
+        visitor.run(&mut pandoc);
+        if pandoc_lipics.debug {
+            eprintln!("{:?}", visitor.kdb);
+        }
+        utils::meta_deep_insert(&mut pandoc.meta, "lipics.knowledge-report", visitor.kdb.report());
+
+        // Introductions link back to their references
+        if output != OutputFormat::Latex {
+            visitor.kdb.backref_decorator().walk_pandoc(&mut pandoc);
+        }
+        // References show their introduction on hover
+        if output == OutputFormat::Html {
+            visitor.kdb.preview_decorator().walk_pandoc(&mut pandoc);
+        }
+
+        if knowledge_index {
+            let content = match output {
+                OutputFormat::Latex => vec![Block::RawBlock(pandoc_ast::Format("latex".to_string()), "\\printindex".to_string())],
+                _ => visitor.kdb.glossary(),
+            };
+            fill_knowledge_index(&mut pandoc.blocks, &content);
+        }
+
+        // We report the problems found while resolving, whatever
+        // the mode, since the knowledge package ignores our scopes
+        let unused_notions = utils::meta_deep_get(&pandoc.meta, "lipics.knowledge.unused-notions")
+            .and_then(|x| utils::meta_to_bool(&x)).unwrap_or(true);
+        let diagnostics = visitor.kdb.diagnostics(unused_notions);
+        match check_diagnostics(&diagnostics, pandoc_lipics.strict) {
+            Ok(warnings) => for warning in warnings {
+                eprintln!("[lipics] {}", warning);
+            },
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("[lipics] {}", error);
+                }
+                std::process::exit(1);
+            }
+        }
+
+        // Proofs sent to the appendix come first
+        // in the `appendix` variable of the templates
+        let appendix = visitor.thms.take_appendix();
+        if !appendix.is_empty() {
+            let mut blocks = match output {
+                OutputFormat::Latex => appendix_to_latex(appendix),
+                _ => appendix_to_pandoc(appendix),
+            };
+            if let Some(existing) = pandoc.meta.get("appendix").and_then(utils::meta_to_blocks) {
+                blocks.extend(existing);
+            }
+            pandoc.meta.insert("appendix".to_string(), MetaValue::MetaBlocks(blocks));
+        }
+
+        // Outside of LaTeX, there is no \cref: we number
+        // the anchors ourselves and resolve the references
+        // once every anchor is known.
+        if output == OutputFormat::Latex {
+            CrefRewriter::new(labels).walk_pandoc(&mut pandoc);
+        } else {
+            let mut collector = AnchorCollector::new(visitor.thms.take_anchors());
+            collector.walk_vec_block(&mut pandoc.blocks);
+            let mut resolver = ReferenceResolver::new(collector.into_anchors(), labels);
+            resolver.walk_pandoc(&mut pandoc);
+        }
+
+        // The remaining citations are bibliographic ones
+        CitationRewriter::new(bib, parse_citation_words(&pandoc.meta), output == OutputFormat::Latex).walk_pandoc(&mut pandoc);
         pandoc
     });
-    io::stdout().write(s.as_bytes()).unwrap();
+    io::stdout().write_all(s.as_bytes()).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lipics_filter::knowledges::KnowledgeBase;
+
+    fn knowledge_base(yaml: &str) -> KnowledgeBase {
+        let value: serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+        let meta = BTreeMap::from([("knowledges".to_string(), utils::yaml_to_meta(&value).unwrap())]);
+        parse_knowledge_base(&meta)
+    }
+
+    fn span(class: &str, text: &str) -> Inline {
+        Inline::Span(("".into(), vec![class.into()], vec![]), vec![Inline::Str(text.into())])
+    }
+
+    fn scoped(scope: &str) -> pandoc_ast::Attr {
+        ("".into(), vec![], vec![("kl-scope".into(), scope.into())])
+    }
+
+    #[test]
+    fn scoped_theorem_title() {
+        let db = knowledge_base("
+- {synonyms: [run]}
+- {name: play, synonyms: [{name: run, scope: games}]}
+");
+        let mut visitor = MyVisitor {
+            kdb: KnowledgeResolver::new(db), mode: OutputMode::FastLatex,
+            thms: theorems::Context::new(), format: OutputFormat::Latex,
+            section: None, context: String::new(), position: 0,
+            section_scopes: vec![], div_scopes: vec![], collecting: false,
+        };
+        let mut pandoc = pandoc_ast::Pandoc {
+            meta: BTreeMap::new(),
+            blocks: vec![
+                Block::Div(scoped("games"), vec![Block::Para(vec![span("ref", "run")])]),
+                Block::Div(("".into(), vec!["definition".into()], vec![]), vec![
+                    Block::Header(3, scoped("games"), vec![Inline::Str("Plays".into())]),
+                    Block::Para(vec![span("intro", "run")]),
+                ]),
+            ],
+            pandoc_api_version: vec![1, 23],
+        };
+        visitor.run(&mut pandoc);
+        let latex = |block: &Block| match block {
+            Block::Div(_, blocks) => match &blocks[0] {
+                Block::Para(inlines) => utils::stringify_inlines(inlines),
+                other => panic!("unexpected {:?}", other),
+            },
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(latex(&pandoc.blocks[0]), "\\aklref{kl-play}{run}");
+        let intro = pandoc.blocks.iter().find_map(|block| match block {
+            Block::Para(inlines) => Some(utils::stringify_inlines(inlines)),
+            _ => None,
+        });
+        assert_eq!(intro.as_deref(), Some("\\akldef{kl-play}{run}"));
+    }
 }
@@ -1,7 +1,7 @@
 /// Some simple polyregular functions on vectors.
 ///
 /// This is not optimised, and will probably never be.
-
+///
 /// This function splits a vector of either A or B
 /// into a prefix of Bs, followed by A's with blocks of B's.
 ///
@@ -8,10 +8,13 @@
 /// @my-theorem         -> Theorem 7
 /// @undefined-label    -> ???
 ///
-use pandoc_ast::Inline;
+use pandoc_ast::{Block, Citation, CitationMode, Inline, MathType, MutVisitor};
+use std::collections::{HashMap, HashSet};
+
+use crate::citations::clean_suffix;
 
 /// Possible anchors in the document
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AnchorKind {
     Theorem,
     Lemma,
@@ -25,23 +28,611 @@ pub enum AnchorKind {
     Definition,
     Remark,
     Example,
+    Observation,
     Proof,
     Item,
     Equation,
     Section,
+    Custom(String),
+}
+
+impl AnchorKind {
+    /// The name of the anchor kind, as cleveref
+    /// would print it (capitalised, not abbreviated).
+    pub fn name(&self) -> String {
+        match self {
+            AnchorKind::Theorem => "Theorem".to_string(),
+            AnchorKind::Lemma => "Lemma".to_string(),
+            AnchorKind::Corollary => "Corollary".to_string(),
+            AnchorKind::Proposition => "Proposition".to_string(),
+            AnchorKind::Conjecture => "Conjecture".to_string(),
+            AnchorKind::Claim => "Claim".to_string(),
+            AnchorKind::Figure => "Figure".to_string(),
+            AnchorKind::Algorithm => "Algorithm".to_string(),
+            AnchorKind::Table => "Table".to_string(),
+            AnchorKind::Definition => "Definition".to_string(),
+            AnchorKind::Remark => "Remark".to_string(),
+            AnchorKind::Example => "Example".to_string(),
+            AnchorKind::Observation => "Observation".to_string(),
+            AnchorKind::Proof => "Proof".to_string(),
+            AnchorKind::Item => "Item".to_string(),
+            AnchorKind::Equation => "Equation".to_string(),
+            AnchorKind::Section => "Section".to_string(),
+            AnchorKind::Custom(s) => s.clone(),
+        }
+    }
+
+    /// The plural name of the anchor kind
+    /// Lemma -> Lemmas, Corollary -> Corollaries,
+    /// Hypothesis -> Hypotheses, Axiom -> Axioms
+    pub fn plural(&self) -> String {
+        let name = self.name();
+        let consonant_y = name.strip_suffix('y')
+            .filter(|stem| stem.ends_with(|c: char| !"aeiouAEIOU".contains(c)));
+        if let Some(stem) = name.strip_suffix("is") {
+            format!("{}es", stem)
+        } else if let Some(stem) = consonant_y {
+            format!("{}ies", stem)
+        } else if ["s", "x", "z", "ch", "sh"].iter().any(|e| name.ends_with(e)) {
+            format!("{}es", name)
+        } else {
+            format!("{}s", name)
+        }
+    }
 }
 
 /// An anchor in the document
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Anchor {
-    label: String,
-    title: Option<Vec<Inline>>,
-    kind: AnchorKind,
+    pub label: String,
+    pub title: Option<Vec<Inline>>,
+    pub kind: AnchorKind,
+    pub number: Option<String>,
 }
 
 /// A reference in the document
 #[derive(Debug)]
 pub struct Reference {
-    label: String,
-    kind: AnchorKind,
+    pub label: String,
+    pub kind: AnchorKind,
+}
+
+/// Prefixes that denote a label rather than a bibliography key,
+/// as in @thm:main or @sec:intro. Citations to such keys
+/// that cannot be resolved are displayed as "???".
+const LABEL_PREFIXES: [&str; 17] = [
+    "thm", "lem", "cor", "prop", "conj", "claim", "def", "rem",
+    "ex", "obs", "sec", "fig", "tab", "tbl", "eq", "alg", "app",
+];
+
+/// Checks whether a citation key looks like a label
+pub fn is_label_like(key: &str) -> bool {
+    match key.split_once(':') {
+        Some((prefix, _)) => LABEL_PREFIXES.contains(&prefix),
+        None => false,
+    }
+}
+
+/// Collects every identifier of the document,
+/// to tell labels apart from bibliography keys.
+#[derive(Debug, Default)]
+pub struct IdCollector {
+    pub ids: HashSet<String>,
+}
+
+impl MutVisitor for IdCollector {
+    fn visit_attr(&mut self, attr: &mut pandoc_ast::Attr) {
+        if !attr.0.is_empty() {
+            self.ids.insert(attr.0.clone());
+        }
+    }
+}
+
+/// Tells apart the keys of `@key` that refer to labels
+/// of the document from those of the bibliography.
+///
+/// A key is a label when it is an identifier of the document,
+/// or when it looks like a label (@thm:xxx) and is not in the
+/// bibliography. Otherwise, it is left to citeproc.
+#[derive(Debug, Clone, Default)]
+pub struct Labels {
+    ids: HashSet<String>,
+    bibliography: HashSet<String>,
+}
+
+impl Labels {
+    /// Creates the classifier, warning about the
+    /// keys that are both labels and bibliography entries.
+    /// Such keys are considered to be labels.
+    pub fn new(ids: HashSet<String>, bibliography: HashSet<String>) -> Labels {
+        let mut ambiguous: Vec<&String> = ids.intersection(&bibliography).collect();
+        ambiguous.sort();
+        for key in ambiguous {
+            eprintln!("[lipics] {} is both a label and a bibliography entry, treating it as a label", key);
+        }
+        Labels { ids, bibliography }
+    }
+
+    pub fn is_label(&self, key: &str) -> bool {
+        self.ids.contains(key) || (!self.bibliography.contains(key) && is_label_like(key))
+    }
+}
+
+/// Splits the citations of a Cite element between
+/// labels and bibliography keys, then rewrites the labels
+/// with the given function, keeping the remaining citations
+/// for citeproc.
+fn split_citations<F>(labels: &Labels, inlines: &mut Vec<Inline>, mut rewrite: F)
+where
+    F: FnMut(&[Citation]) -> Vec<Inline>,
+{
+    let mut new_inlines = vec![];
+    for inline in inlines.drain(..) {
+        match inline {
+            Inline::Cite(citations, content) => {
+                let (refs, cites): (Vec<Citation>, Vec<Citation>) =
+                    citations.into_iter().partition(|c| labels.is_label(&c.citationId));
+                if refs.is_empty() {
+                    new_inlines.push(Inline::Cite(cites, content));
+                } else if cites.is_empty() {
+                    new_inlines.extend(rewrite(&refs));
+                } else {
+                    new_inlines.extend(rewrite(&refs));
+                    new_inlines.push(Inline::Space);
+                    new_inlines.push(Inline::Cite(cites, content));
+                }
+            }
+            _ => new_inlines.push(inline),
+        }
+    }
+    *inlines = new_inlines;
+}
+
+/// Rewrites the citations of labels as cleveref commands
+/// for LaTeX outputs
+///
+/// @thm:a                -> \cref{thm:a}
+/// [@thm:a; @thm:b]      -> \cref{thm:a,thm:b}
+/// -@thm:a               -> \labelcref{thm:a}
+#[derive(Debug)]
+pub struct CrefRewriter {
+    labels: Labels,
+}
+
+impl CrefRewriter {
+    pub fn new(labels: Labels) -> CrefRewriter {
+        CrefRewriter { labels }
+    }
+}
+
+/// Turns a list of label citations into \cref commands:
+/// the prefixes and suffixes of the middle citations
+/// split the command, as in the other formats
+///
+/// [@thm:a; @thm:b]                -> \cref{thm:a,thm:b}
+/// [@thm:a, p. 2; @thm:b]          -> \cref{thm:a} p. 2 and \cref{thm:b}
+/// [see @thm:a; also @thm:b, p. 2] -> see \cref{thm:a} and also \cref{thm:b} p. 2
+fn citations_to_cref(citations: &[Citation]) -> Vec<Inline> {
+    let format = pandoc_ast::Format("latex".to_string());
+    let command = match citations.first().map(|c| c.citationMode) {
+        Some(CitationMode::SuppressAuthor) => "labelcref",
+        _ => "cref",
+    };
+    let mut groups: Vec<Vec<&Citation>> = vec![];
+    for c in citations {
+        match groups.last_mut() {
+            Some(group) if c.citationPrefix.is_empty() && group.last().is_some_and(|l| l.citationSuffix.is_empty()) => group.push(c),
+            _ => groups.push(vec![c]),
+        }
+    }
+    let items = groups.into_iter().map(|group| {
+        let keys: Vec<&str> = group.iter().map(|c| c.citationId.as_str()).collect();
+        let mut inlines = vec![];
+        if !group[0].citationPrefix.is_empty() {
+            inlines.extend(group[0].citationPrefix.clone());
+            inlines.push(Inline::Space);
+        }
+        inlines.push(Inline::RawInline(format.clone(), format!("\\{}{{{}}}", command, keys.join(","))));
+        if let Some(suffix) = group.last().map(|c| clean_suffix(&c.citationSuffix)).filter(|s| !s.is_empty()) {
+            inlines.push(Inline::Space);
+            inlines.extend(suffix);
+        }
+        inlines
+    }).collect();
+    conjunction(items)
+}
+
+impl MutVisitor for CrefRewriter {
+    fn visit_vec_inline(&mut self, inlines: &mut Vec<Inline>) {
+        split_citations(&self.labels, inlines, citations_to_cref);
+        self.walk_vec_inline(inlines);
+    }
+}
+
+/// Collects the anchors of the document that are not
+/// theorems: sections, figures, tables and equations.
+/// They are numbered in order of appearance, following
+/// the numbering of pandoc's `--number-sections`.
+///
+/// Displayed equations in a span with an identifier
+/// are tagged with their number, so that the rendered
+/// number matches the references.
+#[derive(Debug)]
+pub struct AnchorCollector {
+    anchors: HashMap<String, Anchor>,
+    sections: Vec<u32>,
+    figures: u32,
+    tables: u32,
+    equations: u32,
+}
+
+impl AnchorCollector {
+    /// Creates a collector from already known anchors
+    /// (typically the theorems of the document).
+    pub fn new(anchors: HashMap<String, Anchor>) -> AnchorCollector {
+        AnchorCollector { anchors, sections: vec![], figures: 0, tables: 0, equations: 0 }
+    }
+
+    /// The anchors collected so far
+    pub fn into_anchors(self) -> HashMap<String, Anchor> {
+        self.anchors
+    }
+
+    fn insert(&mut self, label: &str, kind: AnchorKind, number: String, title: Option<Vec<Inline>>) {
+        if label.is_empty() {
+            return;
+        }
+        if self.anchors.contains_key(label) {
+            eprintln!("[lipics] duplicate label {}", label);
+        }
+        self.anchors.insert(label.to_string(), Anchor {
+            label: label.to_string(),
+            title,
+            kind,
+            number: Some(number),
+        });
+    }
+
+    fn next_section(&mut self, level: usize) -> String {
+        if self.sections.len() < level {
+            self.sections.resize(level, 0);
+        }
+        self.sections.truncate(level);
+        self.sections[level - 1] += 1;
+        self.sections.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(".")
+    }
+}
+
+impl MutVisitor for AnchorCollector {
+    fn visit_block(&mut self, block: &mut Block) {
+        match block {
+            Block::Header(lvl, (ident, classes, _), inlines)
+                if !classes.iter().any(|c| c == "unnumbered") && *lvl > 0 =>
+            {
+                let number = self.next_section(*lvl as usize);
+                self.insert(ident, AnchorKind::Section, number, Some(inlines.clone()));
+            }
+            Block::Figure((ident, _, _), _, _) => {
+                self.figures += 1;
+                let number = self.figures.to_string();
+                self.insert(ident, AnchorKind::Figure, number, None);
+            }
+            Block::Table((ident, _, _), _, _, _, _, _) => {
+                self.tables += 1;
+                let number = self.tables.to_string();
+                self.insert(ident, AnchorKind::Table, number, None);
+            }
+            _ => {}
+        }
+        self.walk_block(block);
+    }
+
+    fn visit_inline(&mut self, inline: &mut Inline) {
+        if let Inline::Span((ident, _, _), inlines) = inline {
+            if !ident.is_empty() {
+                if let Some(Inline::Math(MathType::DisplayMath, math)) = inlines
+                    .iter_mut()
+                    .find(|i| matches!(i, Inline::Math(MathType::DisplayMath, _)))
+                {
+                    self.equations += 1;
+                    let number = self.equations.to_string();
+                    math.push_str(&format!(" \\tag{{{}}}", number));
+                    let label = ident.clone();
+                    self.insert(&label, AnchorKind::Equation, number, None);
+                }
+            }
+        }
+        self.walk_inline(inline);
+    }
+}
+
+/// Rewrites citations to anchors of the document
+/// into links, the way cleveref would print them.
+///
+/// [@thm:a; @thm:b; @sec:intro] -> Theorems 1 and 2, and Section 3
+/// -@thm:a                     -> 1
+/// @thm:undefined              -> ???
+///
+/// Citations to the bibliography are left
+/// untouched for citeproc.
+#[derive(Debug)]
+pub struct ReferenceResolver {
+    anchors: HashMap<String, Anchor>,
+    labels: Labels,
+}
+
+impl ReferenceResolver {
+    pub fn new(anchors: HashMap<String, Anchor>, labels: Labels) -> ReferenceResolver {
+        ReferenceResolver { anchors, labels }
+    }
+
+    fn resolve(&self, citations: &[Citation]) -> Vec<Inline> {
+        let mut groups: Vec<(Option<AnchorKind>, Vec<&Citation>)> = vec![];
+        for citation in citations {
+            let kind = self.anchors.get(&citation.citationId).map(|a| a.kind.clone());
+            match groups.last_mut() {
+                Some((k, cs)) if kind.is_some() && *k == kind => cs.push(citation),
+                _ => groups.push((kind, vec![citation])),
+            }
+        }
+
+        let suppress = citations
+            .first()
+            .map(|c| c.citationMode == CitationMode::SuppressAuthor)
+            .unwrap_or(false);
+
+        let mut rendered = vec![];
+        for (kind, cs) in groups {
+            let mut items = vec![];
+            for (i, c) in cs.iter().enumerate() {
+                let mut item = c.citationPrefix.clone();
+                if !item.is_empty() {
+                    item.push(Inline::Space);
+                }
+                match (&kind, self.anchors.get(&c.citationId)) {
+                    (Some(kind), Some(anchor)) => {
+                        let mut number = anchor.number.clone().unwrap_or_else(|| "???".to_string());
+                        if *kind == AnchorKind::Equation {
+                            number = format!("({})", number);
+                        }
+                        let mut text = vec![];
+                        if i == 0 && !suppress {
+                            let name = if cs.len() > 1 { kind.plural() } else { kind.name() };
+                            text.push(Inline::Str(name));
+                            text.push(Inline::Space);
+                        }
+                        text.push(Inline::Str(number));
+                        item.push(Inline::Link(
+                            ("".to_string(), vec!["reference".to_string()], vec![]),
+                            text,
+                            (format!("#{}", c.citationId), "".to_string()),
+                        ));
+                    }
+                    _ => {
+                        eprintln!("[lipics] undefined reference {}", c.citationId);
+                        item.push(Inline::Span(
+                            ("".to_string(), vec!["unresolved-reference".to_string()], vec![("label".to_string(), c.citationId.clone())]),
+                            vec![Inline::Strong(vec![Inline::Str("???".to_string())])],
+                        ));
+                    }
+                }
+                let suffix = clean_suffix(&c.citationSuffix);
+                if !suffix.is_empty() {
+                    item.push(Inline::Space);
+                    item.extend(suffix);
+                }
+                items.push(item);
+            }
+            rendered.push(conjunction(items));
+        }
+        conjunction(rendered)
+    }
+}
+
+/// Joins a list of inlines in the cleveref way,
+/// with the oxford comma of LIPIcs
+/// a                -> a
+/// a, b             -> a and b
+/// a, b, c          -> a, b, and c
+fn conjunction(mut items: Vec<Vec<Inline>>) -> Vec<Inline> {
+    let mut out = vec![];
+    let n = items.len();
+    for (i, item) in items.drain(..).enumerate() {
+        if i > 0 {
+            let s = match (n, i == n - 1) {
+                (2, _) => " and ",
+                (_, true) => ", and ",
+                (_, false) => ", ",
+            };
+            out.extend(separator(s));
+        }
+        out.extend(item);
+    }
+    out
+}
+
+/// Turns a textual separator into inlines
+fn separator(s: &str) -> Vec<Inline> {
+    let mut out = vec![];
+    for (i, word) in s.split(' ').enumerate() {
+        if i > 0 {
+            out.push(Inline::Space);
+        }
+        if !word.is_empty() {
+            out.push(Inline::Str(word.to_string()));
+        }
+    }
+    out
+}
+
+impl MutVisitor for ReferenceResolver {
+    fn visit_vec_inline(&mut self, inlines: &mut Vec<Inline>) {
+        let labels = self.labels.clone();
+        split_citations(&labels, inlines, |citations| self.resolve(citations));
+        self.walk_vec_inline(inlines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The inlines of a text, one word per Str
+    fn words(text: &str) -> Vec<Inline> {
+        let mut inlines = vec![];
+        for word in text.split(' ').filter(|w| !w.is_empty()) {
+            if !inlines.is_empty() {
+                inlines.push(Inline::Space);
+            }
+            inlines.push(Inline::Str(word.to_string()));
+        }
+        inlines
+    }
+
+    /// A citation `prefix @id suffix`
+    fn citation(prefix: &str, id: &str, suffix: &str) -> Citation {
+        Citation {
+            citationId: id.to_string(),
+            citationPrefix: words(prefix),
+            citationSuffix: words(suffix),
+            citationMode: CitationMode::NormalCitation,
+            citationNoteNum: 0,
+            citationHash: 0,
+        }
+    }
+
+    fn labels() -> Labels {
+        Labels::new(HashSet::new(), HashSet::from(["knuth".to_string()]))
+    }
+
+    fn cref(citations: Vec<Citation>) -> String {
+        let mut inlines = vec![Inline::Cite(citations, vec![])];
+        CrefRewriter::new(labels()).visit_vec_inline(&mut inlines);
+        crate::utils::stringify_inlines(&inlines)
+    }
+
+    #[test]
+    fn cref_groups() {
+        assert_eq!(cref(vec![citation("", "thm:a", "")]), "\\cref{thm:a}");
+        assert_eq!(cref(vec![citation("", "thm:a", ""), citation("", "thm:b", ""), citation("", "sec:c", "")]),
+                   "\\cref{thm:a,thm:b,sec:c}");
+        assert_eq!(cref(vec![citation("see", "thm:a", ""), citation("also", "thm:b", "")]),
+                   "see \\cref{thm:a} and also \\cref{thm:b}");
+        let mut suppressed = citation("", "thm:a", "");
+        suppressed.citationMode = CitationMode::SuppressAuthor;
+        assert_eq!(cref(vec![suppressed]), "\\labelcref{thm:a}");
+    }
+
+    #[test]
+    fn cref_suffixes() {
+        assert_eq!(cref(vec![citation("", "thm:a", ", p. 2")]), "\\cref{thm:a} p. 2");
+        assert_eq!(cref(vec![citation("", "thm:a", "p. 2")]), "\\cref{thm:a} p. 2");
+        assert_eq!(cref(vec![citation("", "thm:a", ", p. 2"), citation("", "thm:b", "")]),
+                   "\\cref{thm:a} p. 2 and \\cref{thm:b}");
+        assert_eq!(cref(vec![citation("see", "thm:a", ""), citation("also", "thm:b", ", p. 2")]),
+                   "see \\cref{thm:a} and also \\cref{thm:b} p. 2");
+    }
+
+    #[test]
+    fn cref_keeps_the_bibliography() {
+        let mut inlines = vec![Inline::Cite(vec![citation("", "thm:a", ""), citation("", "knuth", "")], vec![])];
+        CrefRewriter::new(labels()).visit_vec_inline(&mut inlines);
+        assert_eq!(inlines[..2], [
+            Inline::RawInline(pandoc_ast::Format("latex".to_string()), "\\cref{thm:a}".to_string()),
+            Inline::Space,
+        ]);
+        assert_eq!(inlines[2], Inline::Cite(vec![citation("", "knuth", "")], vec![]));
+    }
+
+    fn anchor(label: &str, kind: AnchorKind, number: &str) -> (String, Anchor) {
+        (label.to_string(), Anchor { label: label.to_string(), title: None, kind, number: Some(number.to_string()) })
+    }
+
+    fn resolve(citations: Vec<Citation>) -> String {
+        let anchors = HashMap::from([
+            anchor("thm:a", AnchorKind::Theorem, "1"),
+            anchor("thm:b", AnchorKind::Theorem, "2"),
+            anchor("thm:c", AnchorKind::Theorem, "4"),
+            anchor("hyp:a", AnchorKind::Custom("Hypothesis".to_string()), "3"),
+            anchor("hyp:b", AnchorKind::Custom("Hypothesis".to_string()), "5"),
+            anchor("sec:a", AnchorKind::Section, "2.1"),
+            anchor("eq:a", AnchorKind::Equation, "1"),
+        ]);
+        // hyp:xxx is not label-like, but it is an identifier of the document
+        let labels = Labels::new(anchors.keys().cloned().collect(), HashSet::new());
+        let mut inlines = vec![Inline::Cite(citations, vec![])];
+        ReferenceResolver::new(anchors, labels).visit_vec_inline(&mut inlines);
+        crate::utils::stringify_inlines(&inlines)
+    }
+
+    #[test]
+    fn plurals() {
+        assert_eq!(AnchorKind::Lemma.plural(), "Lemmas");
+        assert_eq!(AnchorKind::Corollary.plural(), "Corollaries");
+        assert_eq!(AnchorKind::Proof.plural(), "Proofs");
+        assert_eq!(AnchorKind::Custom("Hypothesis".to_string()).plural(), "Hypotheses");
+        assert_eq!(AnchorKind::Custom("Process".to_string()).plural(), "Processes");
+        assert_eq!(AnchorKind::Custom("Key".to_string()).plural(), "Keys");
+    }
+
+    #[test]
+    fn resolution() {
+        assert_eq!(resolve(vec![citation("", "thm:a", "")]), "Theorem 1");
+        assert_eq!(resolve(vec![citation("", "thm:a", ""), citation("", "thm:b", "")]), "Theorems 1 and 2");
+        assert_eq!(resolve(vec![citation("", "thm:a", ""), citation("", "thm:b", ""), citation("", "thm:c", "")]),
+                   "Theorems 1, 2, and 4");
+        assert_eq!(resolve(vec![citation("", "hyp:a", ""), citation("", "hyp:b", "")]), "Hypotheses 3 and 5");
+        assert_eq!(resolve(vec![citation("", "thm:a", ""), citation("", "sec:a", "")]), "Theorem 1 and Section 2.1");
+        assert_eq!(resolve(vec![citation("", "eq:a", "")]), "Equation (1)");
+        let mut suppressed = citation("", "thm:b", "");
+        suppressed.citationMode = CitationMode::SuppressAuthor;
+        assert_eq!(resolve(vec![suppressed]), "2");
+        assert_eq!(resolve(vec![citation("", "thm:undefined", "")]), "???");
+    }
+
+    #[test]
+    fn resolution_suffixes() {
+        assert_eq!(resolve(vec![citation("", "thm:a", ", p. 2")]), "Theorem 1 p. 2");
+        assert_eq!(resolve(vec![citation("see", "thm:a", ""), citation("", "thm:b", ", item 3")]),
+                   "see Theorems 1 and 2 item 3");
+    }
+
+    #[test]
+    fn numbering() {
+        let span = |id: &str, math: &str| Inline::Span(
+            (id.to_string(), vec![], vec![]),
+            vec![Inline::Math(MathType::DisplayMath, math.to_string())],
+        );
+        let header = |level: i64, id: &str, classes: &[&str]| Block::Header(
+            level,
+            (id.to_string(), classes.iter().map(|c| c.to_string()).collect(), vec![]),
+            words(id),
+        );
+        let figure = |id: &str| Block::Figure((id.to_string(), vec![], vec![]), (None, vec![]), vec![]);
+        let mut blocks = vec![
+            header(1, "sec:a", &[]),
+            header(2, "sec:a1", &[]),
+            figure("fig:a"),
+            Block::Para(vec![span("eq:a", "x"), span("", "y"), span("eq:b", "z")]),
+            header(2, "sec:a2", &[]),
+            header(1, "sec:acks", &["unnumbered"]),
+            header(1, "sec:b", &[]),
+            header(3, "sec:b11", &[]),
+            figure("fig:b"),
+        ];
+        let mut collector = AnchorCollector::new(HashMap::new());
+        collector.walk_vec_block(&mut blocks);
+        let anchors = collector.into_anchors();
+        let mut numbers: Vec<(String, String)> = anchors.values()
+            .map(|a| (format!("{} {}", a.kind.name(), a.label), a.number.clone().unwrap()))
+            .collect();
+        numbers.sort();
+        assert_eq!(numbers, [
+            ("Equation eq:a", "1"), ("Equation eq:b", "2"),
+            ("Figure fig:a", "1"), ("Figure fig:b", "2"),
+            ("Section sec:a", "1"), ("Section sec:a1", "1.1"), ("Section sec:a2", "1.2"),
+            ("Section sec:b", "2"), ("Section sec:b11", "2.0.1"),
+        ].map(|(a, n)| (a.to_string(), n.to_string())));
+        assert_eq!(blocks[3], Block::Para(vec![span("eq:a", "x \\tag{1}"), span("", "y"), span("eq:b", "z \\tag{2}")]));
+    }
 }
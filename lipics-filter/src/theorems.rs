@@ -8,29 +8,115 @@
 /// - use the "anchor kind" to determine the type of the environment
 /// - allow to have "links" between environments (proof-of)
 /// - allow to have "references" to environments (cleveref)
-///     parse @reference   -> \cref{reference} (if exists)
+///   parse @reference   -> \cref{reference} (if exists)
 /// - allow to restate environments (thm-restate)
-use pandoc_ast::{Block, Inline};
-use std::collections::{HashMap, HashSet};
+use pandoc_ast::{Block, Inline, MetaValue, MutVisitor};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use crate::polyreg;
-use crate::references::Anchor;
+use crate::utils;
+use crate::references::{Anchor, AnchorKind};
 
 /// Theorem type in the lipics format.
 /// We provide a few standard types
 /// plus a custom type that can be used
 /// for any other type of theorem that is not
 /// covered by the standard ones.
-#[derive(Debug)]
-enum TheoremKind {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TheoremKind {
     Theorem,
     Lemma,
     Corollary,
     Proposition,
     Conjecture,
     Claim,
+    Definition,
+    Example,
+    Remark,
+    Observation,
+    ClaimProof,
+    Declared(TheoremDeclaration),
     Custom(String),
 }
 
+/// A theorem-like environment declared in the metadata
+/// of the document.
+///
+/// lipics:
+///   theorems:
+///     - name: hypothesis
+///       counter: theorem
+///       style: plain
+///       title: Hypothesis
+///
+/// Without a counter, the environment has its own numbering.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TheoremDeclaration {
+    pub name: String,
+    pub title: String,
+    pub counter: Option<String>,
+    pub style: Option<String>,
+}
+
+impl TheoremKind {
+    /// The name of the environment as displayed
+    /// in the document (e.g. "Theorem").
+    pub fn name(&self) -> String {
+        match self {
+            TheoremKind::Theorem => "Theorem".to_string(),
+            TheoremKind::Lemma => "Lemma".to_string(),
+            TheoremKind::Corollary => "Corollary".to_string(),
+            TheoremKind::Proposition => "Proposition".to_string(),
+            TheoremKind::Conjecture => "Conjecture".to_string(),
+            TheoremKind::Claim => "Claim".to_string(),
+            TheoremKind::Definition => "Definition".to_string(),
+            TheoremKind::Example => "Example".to_string(),
+            TheoremKind::Remark => "Remark".to_string(),
+            TheoremKind::Observation => "Observation".to_string(),
+            TheoremKind::ClaimProof => "Proof".to_string(),
+            TheoremKind::Declared(d) => d.title.clone(),
+            TheoremKind::Custom(s) => {
+                let mut chars = s.chars();
+                match chars.next() {
+                    Some(c) => c.to_uppercase().chain(chars).collect(),
+                    None => String::new(),
+                }
+            }
+        }
+    }
+
+    /// Whether the environment is numbered. Every numbered
+    /// environment shares the theorem counter, as in LIPIcs,
+    /// while claim proofs are never numbered.
+    pub fn is_numbered(&self) -> bool {
+        !matches!(self, TheoremKind::ClaimProof)
+    }
+
+    /// The counter used to number the environment.
+    /// The standard environments all share the theorem counter,
+    /// and declared environments follow the counters of the
+    /// other declarations up to the root counter:
+    /// b with counter a, a with counter theorem -> theorem
+    fn counter(&self, declared: &[TheoremDeclaration]) -> String {
+        let mut d = match self {
+            TheoremKind::Declared(d) => d,
+            _ => return "theorem".to_string(),
+        };
+        let mut seen = HashSet::from([d.name.clone()]);
+        while let Some(c) = &d.counter {
+            if TheoremKind::try_from(c.as_str()).is_ok() {
+                return "theorem".to_string();
+            }
+            match declared.iter().find(|other| &other.name == c) {
+                Some(other) if seen.insert(c.clone()) => d = other,
+                // a cycle of counters falls back to the shared one
+                Some(_) => return "theorem".to_string(),
+                None => return c.clone(),
+            }
+        }
+        d.name.clone()
+    }
+}
+
 impl TryFrom<&str> for TheoremKind {
     type Error = ();
     fn try_from(s: &str) -> Result<Self, ()> {
@@ -41,6 +127,11 @@ impl TryFrom<&str> for TheoremKind {
             "proposition" => Ok(TheoremKind::Proposition),
             "conjecture" => Ok(TheoremKind::Conjecture),
             "claim" => Ok(TheoremKind::Claim),
+            "definition" => Ok(TheoremKind::Definition),
+            "example" => Ok(TheoremKind::Example),
+            "remark" => Ok(TheoremKind::Remark),
+            "observation" => Ok(TheoremKind::Observation),
+            "claimproof" => Ok(TheoremKind::ClaimProof),
             _ if s.starts_with("custom:") => Ok(TheoremKind::Custom(
                 s.strip_prefix("custom:").unwrap().to_string(),
             )),
@@ -58,21 +149,107 @@ impl From<TheoremKind> for String {
             TheoremKind::Proposition => "proposition".to_string(),
             TheoremKind::Conjecture => "conjecture".to_string(),
             TheoremKind::Claim => "claim".to_string(),
-            TheoremKind::Custom(s) => format!("{}", s),
+            TheoremKind::Definition => "definition".to_string(),
+            TheoremKind::Example => "example".to_string(),
+            TheoremKind::Remark => "remark".to_string(),
+            TheoremKind::Observation => "observation".to_string(),
+            TheoremKind::ClaimProof => "claimproof".to_string(),
+            TheoremKind::Declared(d) => d.name,
+            TheoremKind::Custom(s) => s,
+        }
+    }
+}
+
+impl From<&TheoremKind> for AnchorKind {
+    fn from(tt: &TheoremKind) -> AnchorKind {
+        match tt {
+            TheoremKind::Theorem => AnchorKind::Theorem,
+            TheoremKind::Lemma => AnchorKind::Lemma,
+            TheoremKind::Corollary => AnchorKind::Corollary,
+            TheoremKind::Proposition => AnchorKind::Proposition,
+            TheoremKind::Conjecture => AnchorKind::Conjecture,
+            TheoremKind::Claim => AnchorKind::Claim,
+            TheoremKind::Definition => AnchorKind::Definition,
+            TheoremKind::Example => AnchorKind::Example,
+            TheoremKind::Remark => AnchorKind::Remark,
+            TheoremKind::Observation => AnchorKind::Observation,
+            TheoremKind::ClaimProof => AnchorKind::Proof,
+            TheoremKind::Declared(_) | TheoremKind::Custom(_) => AnchorKind::Custom(tt.name()),
         }
     }
 }
 
 /// Checks whether a list of classes contains a theorem type.
-/// It can be because it is a standard type, or a custom type
-/// in which case it is written "custom:<name>"
-fn to_theorem_type(classes: &[String]) -> Option<TheoremKind> {
+/// It can be because it is a standard type, a type declared
+/// in the metadata, or a custom type in which case it is
+/// written "custom:<name>"
+fn to_theorem_type(declared: &[TheoremDeclaration], classes: &[String]) -> Option<TheoremKind> {
     for class in classes {
         if let Ok(tt) = TheoremKind::try_from(class.as_str()) {
             return Some(tt);
         }
+        if let Some(d) = declared.iter().find(|d| &d.name == class) {
+            return Some(TheoremKind::Declared(d.clone()));
+        }
     }
-    return None;
+    None
+}
+
+/// Parses a theorem declaration, either a map
+/// { name, counter, style, title } or simply a name.
+fn parse_theorem_declaration(meta: &MetaValue) -> Option<TheoremDeclaration> {
+    let (name, counter, style, title) = match meta {
+        MetaValue::MetaMap(m) => (
+            utils::meta_to_string(m.get("name")?)?,
+            m.get("counter").and_then(|x| utils::meta_to_string(x)),
+            m.get("style").and_then(|x| utils::meta_to_string(x)),
+            m.get("title").and_then(|x| utils::meta_to_inline(x)).map(|i| utils::stringify_inlines(&i)),
+        ),
+        _ => (utils::meta_to_string(meta)?, None, None, None),
+    };
+    let title = title.unwrap_or_else(|| TheoremKind::Custom(name.clone()).name());
+    Some(TheoremDeclaration { name, title, counter, style })
+}
+
+/// Parses the theorem-like environments declared
+/// in `lipics.theorems`. Declarations that would
+/// redefine a standard environment are ignored.
+pub fn parse_theorem_declarations(meta: &BTreeMap<String, MetaValue>) -> Vec<TheoremDeclaration> {
+    match utils::meta_deep_get(meta, "lipics.theorems") {
+        Some(MetaValue::MetaList(l)) => l
+            .iter()
+            .filter_map(parse_theorem_declaration)
+            .filter(|d| {
+                let builtin = TheoremKind::try_from(d.name.as_str()).is_ok();
+                if builtin {
+                    eprintln!("[lipics] ignoring the declaration of the standard environment {}", d.name);
+                }
+                !builtin
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// The LaTeX declarations of the theorem-like environments
+/// declared in the metadata, to be added to `header-includes`.
+///
+/// \theoremstyle{plain}
+/// \newtheorem{hypothesis}[theorem]{Hypothesis}
+pub fn declarations_to_latex(declared: &[TheoremDeclaration]) -> Block {
+    let mut lines = vec![];
+    for d in declared {
+        lines.push(format!("\\theoremstyle{{{}}}", d.style.as_deref().unwrap_or("plain")));
+        match &d.counter {
+            Some(_) => {
+                let counter = TheoremKind::Declared(d.clone()).counter(declared);
+                lines.push(format!("\\newtheorem{{{}}}[{}]{{{}}}", d.name, counter, utils::escape_latex(&d.title)))
+            }
+            None => lines.push(format!("\\newtheorem{{{}}}{{{}}}", d.name, utils::escape_latex(&d.title))),
+        }
+    }
+    lines.push("\\theoremstyle{plain}".to_string());
+    Block::RawBlock(pandoc_ast::Format("latex".to_string()), lines.join("\n"))
 }
 
 /// A theorem in the lipics format.
@@ -82,37 +259,46 @@ fn to_theorem_type(classes: &[String]) -> Option<TheoremKind> {
 /// potential proof elements
 /// and contains a list of blocks for
 /// the statement of the theorem.
-#[derive(Debug)]
-struct Theorem {
-    title: Option<Vec<Inline>>,
-    kind: TheoremKind,
-    label: Option<String>,
-    restatable: Option<String>,
-    proofs: Vec<Proof>,
-    statement: Vec<Block>,
-    classes: HashSet<String>,
-    keyvals: HashMap<String, String>,
-}
-
-fn theorem_to_latex(thm: Theorem) -> Vec<Block> {
+/// Theorems are numbered in order of appearance,
+/// all kinds sharing the same counter as in LIPIcs,
+/// except for the unnumbered ones.
+#[derive(Debug, Clone)]
+pub struct Theorem {
+    pub title: Option<Vec<Inline>>,
+    pub kind: TheoremKind,
+    pub number: Option<u32>,
+    pub label: Option<String>,
+    pub restatable: Option<String>,
+    pub proofs: Vec<Proof>,
+    pub statement: Vec<Block>,
+    pub classes: HashSet<String>,
+    pub keyvals: HashMap<String, String>,
+    /// The appendix sections containing the
+    /// proofs of this theorem (anchor, number)
+    pub appendix: Vec<(String, String)>,
+}
+
+pub fn theorem_to_latex(thm: Theorem) -> Vec<Block> {
+    if thm.kind == TheoremKind::ClaimProof {
+        return claimproof_to_latex(thm);
+    }
     let thmtype = String::from(thm.kind);
 
     let mut blocks = vec![];
     let format = pandoc_ast::Format("latex".to_string());
 
-    let start_block = format!("\\begin{{{}}}", thmtype);
     let end_block = format!("\\end{{{}}}", thmtype);
 
     // We create the following output
     //
-    // \begin{thm_kind}[label={label}, restatable={restatable}, title={title}]
+    // \begin{thm_kind}[label={label}, restate={restatable}, title={title}]
     // statement
     // \end{thm_kind}
     //
     // the first line is a Block::Plain block
     // containing a vector of
     // Inline::RawInline(format, "\\begin{{{thm_kind}}}"),
-    // Inline::RawInline(format, "label={label}, restatable={restatable}, title={"),
+    // Inline::RawInline(format, "label={label}, restate={restatable}, title={"),
     // title
     // Inline::RawInline(format, "}]")
 
@@ -123,12 +309,12 @@ fn theorem_to_latex(thm: Theorem) -> Vec<Block> {
                 Inline::RawInline(format.clone(), format!("\\begin{{{}}}[", thmtype)),
             );
             let opts: Vec<String> = vec![
-                thm.label.map(|l| format!("label={}", l)),
-                thm.restatable.map(|r| format!("restatable={}", r)),
+                thm.label.map(|l| format!("label={{{}}}", l)),
+                thm.restatable.map(|r| format!("restate={{{}}}", r)),
                 Some("title={".to_string()),
             ]
             .into_iter()
-            .filter_map(|x| x)
+            .flatten()
             .collect();
 
             let stropts = opts.join(", ");
@@ -142,11 +328,11 @@ fn theorem_to_latex(thm: Theorem) -> Vec<Block> {
                 format!("\\begin{{{}}}", thmtype),
             )];
             let opts: Vec<String> = vec![
-                thm.label.map(|l| format!("label={}", l)),
-                thm.restatable.map(|r| format!("restatable={}", r)),
+                thm.label.map(|l| format!("label={{{}}}", l)),
+                thm.restatable.map(|r| format!("restate={{{}}}", r)),
             ]
             .into_iter()
-            .filter_map(|x| x)
+            .flatten()
             .collect();
             let stropts = opts.join(", ");
             if !stropts.is_empty() {
@@ -158,87 +344,581 @@ fn theorem_to_latex(thm: Theorem) -> Vec<Block> {
 
     blocks.extend(thm.statement);
 
+    for (anchor, _) in thm.appendix {
+        blocks.push(Block::Plain(vec![Inline::RawInline(
+            format.clone(),
+            format!("\\hfill(see proof in \\cref{{{}}})", anchor),
+        )]));
+    }
+
     blocks.push(Block::Plain(vec![Inline::RawInline(
         format.clone(),
         end_block,
     )]));
 
     for proof in thm.proofs {
-        let start_proof = format!("\\begin{{proof}}");
-        let end_proof = format!("\\end{{proof}}");
-        let start = Inline::RawInline(format.clone(), start_proof);
-        let end = Inline::RawInline(format.clone(), end_proof);
+        blocks.extend(proof_to_latex(proof));
+    }
+
+    blocks
+}
+
+/// Transforms a claim proof into a LaTeX claimproof environment.
+/// Like a proof, its title is the optional argument of the
+/// environment and its label follows; claim proofs are
+/// neither restated nor sent to the appendix.
+///
+/// \begin{claimproof}[title]\label{label}
+fn claimproof_to_latex(thm: Theorem) -> Vec<Block> {
+    let format = pandoc_ast::Format("latex".to_string());
+    let mut start = vec![Inline::RawInline(format.clone(), "\\begin{claimproof}".to_string())];
+    if let Some(title) = thm.title {
+        start.push(Inline::RawInline(format.clone(), "[".to_string()));
+        start.extend(title);
+        start.push(Inline::RawInline(format.clone(), "]".to_string()));
+    }
+    if let Some(label) = thm.label {
+        start.push(Inline::RawInline(format.clone(), format!("\\label{{{}}}", label)));
+    }
+
+    let mut blocks = vec![Block::Plain(start)];
+    blocks.extend(thm.statement);
+    blocks.push(Block::Plain(vec![Inline::RawInline(format, "\\end{claimproof}".to_string())]));
+    for proof in thm.proofs {
+        blocks.extend(proof_to_latex(proof));
+    }
+    blocks
+}
+
+/// Transforms a proof into a LaTeX proof environment.
+/// The title of the proof (if any) is used as the
+/// optional argument of the environment.
+fn proof_to_latex(proof: Proof) -> Vec<Block> {
+    let format = pandoc_ast::Format("latex".to_string());
+    let mut start = vec![];
+    match (proof.title, proof.kind) {
+        (Some(title), _) => {
+            start.push(Inline::RawInline(format.clone(), "\\begin{proof}[".to_string()));
+            start.extend(title);
+            start.push(Inline::RawInline(format.clone(), "]".to_string()));
+        }
+        (None, ProofKind::Sketch) => {
+            start.push(Inline::RawInline(format.clone(), "\\begin{proof}[Proof Sketch]".to_string()));
+        }
+        (None, ProofKind::Proof) => {
+            start.push(Inline::RawInline(format.clone(), "\\begin{proof}".to_string()));
+        }
+    }
+    if let Some(label) = proof.label {
+        start.push(Inline::RawInline(format.clone(), format!("\\label{{{}}}", label)));
+    }
+
+    let mut blocks = vec![Block::Plain(start)];
+    blocks.extend(proof.body);
+    blocks.push(Block::Plain(vec![Inline::RawInline(format, "\\end{proof}".to_string())]));
+    blocks
+}
+
+/// The heading of a theorem outside of LaTeX
+/// Kind number (title).
+fn theorem_heading(kind: &TheoremKind, number: Option<u32>, title: Option<Vec<Inline>>) -> Vec<Inline> {
+    let mut header = vec![Inline::Str(kind.name())];
+    if let Some(number) = number {
+        header.push(Inline::Space);
+        header.push(Inline::Span(
+            ("".to_string(), vec!["theorem-number".to_string()], vec![]),
+            vec![Inline::Str(number.to_string())],
+        ));
+    }
+    if let Some(title) = title {
+        let mut content = vec![Inline::Str("(".to_string())];
+        content.extend(title);
+        content.push(Inline::Str(")".to_string()));
+        header.push(Inline::Space);
+        header.push(Inline::Span(
+            ("".to_string(), vec!["theorem-title".to_string()], vec![]),
+            content,
+        ));
+    }
+    header.push(Inline::Str(".".to_string()));
+    header
+}
+
+/// The attributes of the div containing a theorem
+/// outside of LaTeX.
+fn theorem_attr(kind: TheoremKind, label: Option<String>, classes: HashSet<String>, keyvals: HashMap<String, String>) -> pandoc_ast::Attr {
+    let mut cls = vec!["theorem".to_string()];
+    if let TheoremKind::Declared(TheoremDeclaration { style: Some(style), .. }) = &kind {
+        cls.push(format!("thm-style-{}", style));
+    }
+    let thmtype = String::from(kind);
+    if thmtype != "theorem" {
+        cls.push(thmtype);
+    }
+    let mut extra: Vec<String> = classes.into_iter().filter(|c| !cls.contains(c)).collect();
+    extra.sort();
+    cls.extend(extra);
+
+    let mut kvs: Vec<(String, String)> = keyvals.into_iter().collect();
+    kvs.sort();
 
-        blocks.push(Block::Plain(vec![start]));
+    (label.unwrap_or_default(), cls, kvs)
+}
 
-        blocks.extend(proof.body);
+/// Transforms a theorem into generic pandoc blocks,
+/// for output formats that do not know about theorem
+/// environments.
+///
+/// ::: {.theorem .lemma #label}
+/// ### Lemma 3 (title).
+/// statement
+/// ::: proof
+/// *Proof.* body
+/// :::
+/// :::
+pub fn theorem_to_pandoc(thm: Theorem) -> Vec<Block> {
+    theorem_to_div(thm, |proof| vec![proof_to_pandoc(proof)])
+}
 
-        blocks.push(Block::Plain(vec![end]));
+/// Transforms a theorem into HTML,
+/// where proofs can be collapsed.
+///
+/// <div class="theorem lemma" id="label">
+/// <h3>Lemma <span class="theorem-number">3</span> (title).</h3>
+/// statement
+/// <details class="proof">
+/// <summary>Proof</summary>
+/// body
+/// </details>
+/// </div>
+pub fn theorem_to_html(thm: Theorem) -> Vec<Block> {
+    theorem_to_div(thm, proof_to_html)
+}
+
+/// The div of a theorem outside of LaTeX: its heading,
+/// its statement, then its proofs rendered by `proof_to_blocks`
+fn theorem_to_div(thm: Theorem, proof_to_blocks: impl Fn(Proof) -> Vec<Block>) -> Vec<Block> {
+    let mut content = vec![Block::Header(
+        3,
+        ("".to_string(), vec!["unnumbered".to_string(), "unlisted".to_string()], vec![]),
+        theorem_heading(&thm.kind, thm.number, thm.title),
+    )];
+    content.extend(thm.statement);
+    content.extend(thm.appendix.into_iter().map(|(anchor, number)| appendix_link(anchor, number)));
+    for proof in thm.proofs {
+        content.extend(proof_to_blocks(proof));
     }
 
+    vec![Block::Div(theorem_attr(thm.kind, thm.label, thm.classes, thm.keyvals), content)]
+}
+
+/// A link from a theorem to its proof in the appendix
+/// (see proof in Appendix A.1)
+fn appendix_link(anchor: String, number: String) -> Block {
+    Block::Para(vec![
+        Inline::Str("(see".to_string()),
+        Inline::Space,
+        Inline::Str("proof".to_string()),
+        Inline::Space,
+        Inline::Str("in".to_string()),
+        Inline::Space,
+        Inline::Link(
+            ("".to_string(), vec![], vec![]),
+            vec![Inline::Str("Appendix".to_string()), Inline::Space, Inline::Str(number)],
+            (format!("#{}", anchor), "".to_string()),
+        ),
+        Inline::Str(")".to_string()),
+    ])
+}
+
+/// Transforms a proof into a collapsible HTML block.
+/// The summary is the title of the proof when it is given,
+/// and otherwise depends on the kind of the proof.
+fn proof_to_html(proof: Proof) -> Vec<Block> {
+    let format = pandoc_ast::Format("html".to_string());
+    let (class, default_summary) = match proof.kind {
+        ProofKind::Proof => ("proof", "Proof"),
+        ProofKind::Sketch => ("proof sketch", "Proof sketch"),
+    };
+    let open = match &proof.label {
+        Some(label) => format!("<details class=\"{}\" id=\"{}\">", class, label),
+        None => format!("<details class=\"{}\">", class),
+    };
+    let summary = proof
+        .title
+        .unwrap_or_else(|| vec![Inline::Str(default_summary.to_string())]);
+
+    let mut blocks = vec![
+        Block::RawBlock(format.clone(), open),
+        Block::RawBlock(format.clone(), "<summary>".to_string()),
+        Block::Plain(summary),
+        Block::RawBlock(format.clone(), "</summary>".to_string()),
+    ];
+    blocks.extend(proof.body);
+    blocks.push(Block::RawBlock(format, "</details>".to_string()));
     blocks
 }
 
-/// Kind of possible anchors
+/// Transforms a proof into a generic pandoc div.
+fn proof_to_pandoc(proof: Proof) -> Block {
+    let title = match (proof.title, proof.kind) {
+        (Some(title), _) => title,
+        (None, ProofKind::Sketch) => vec![Inline::Str("Proof".to_string()), Inline::Space, Inline::Str("Sketch".to_string())],
+        (None, ProofKind::Proof) => vec![Inline::Str("Proof".to_string())],
+    };
+    let mut lead = vec![Inline::Emph(title), Inline::Str(".".to_string())];
+    let mut body = proof.body;
+    // we put the title of the proof in front of the first paragraph
+    // when possible, to mimic the LaTeX rendering
+    match body.first_mut() {
+        Some(Block::Para(inlines)) => {
+            lead.push(Inline::Space);
+            lead.append(inlines);
+            *inlines = lead;
+        }
+        _ => body.insert(0, Block::Para(lead)),
+    }
+
+    let mut classes: Vec<String> = proof.classes.into_iter().collect();
+    classes.sort();
+    classes.insert(0, "proof".to_string());
+    let mut keyvals: Vec<(String, String)> = proof.keyvals.into_iter().collect();
+    keyvals.sort();
+
+    Block::Div((proof.label.unwrap_or_default(), classes, keyvals), body)
+}
 
 /// A context for the conversion.
 #[derive(Debug)]
-struct Context {
-    theorem_counter: u32,
-    label_counter: u32,
+pub struct Context {
+    counters: HashMap<String, u32>,
+    // environments declared in the metadata
+    declared: Vec<TheoremDeclaration>,
     //
     // references
     theorems: HashMap<String, Anchor>,
+    // proofs sent to the appendix
+    appendix: Vec<AppendixProof>,
+    // identifiers that generated labels must avoid
+    taken: HashSet<String>,
+    // number of generated labels, for the whole document
+    generated: usize,
     // forward references (a label points to somewhere)
     // label -> Anchor
     // backward references (a label is pointed by some things)
     // label -> Vec<Anchor>
 }
 
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Context {
-    fn new() -> Context {
+    pub fn new() -> Context {
+        Context::with_declarations(vec![])
+    }
+
+    pub fn with_declarations(declared: Vec<TheoremDeclaration>) -> Context {
         Context {
-            theorem_counter: 0,
-            label_counter: 0,
+            counters: HashMap::new(),
+            declared,
             theorems: HashMap::new(),
+            appendix: vec![],
+            taken: HashSet::new(),
+            generated: 0,
+        }
+    }
+
+    /// Marks identifiers of the document as taken,
+    /// so that generated labels never collide with them
+    pub fn reserve_ids(&mut self, ids: impl IntoIterator<Item = String>) {
+        self.taken.extend(ids);
+    }
+
+    /// A fresh label for a theorem without one,
+    /// made of its kind and a document-wide number
+    /// (hypothesis-3), skipping the identifiers taken.
+    fn fresh_label(&mut self, kind: &TheoremKind) -> String {
+        let prefix: String = String::from(kind.clone())
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect();
+        loop {
+            self.generated += 1;
+            let label = format!("{}-{}", prefix, self.generated);
+            if !self.taken.contains(&label) && !self.theorems.contains_key(&label) {
+                self.taken.insert(label.clone());
+                return label;
+            }
+        }
+    }
+
+    fn next_theorem(&mut self, kind: &TheoremKind) -> u32 {
+        let counter = self.counters.entry(kind.counter(&self.declared)).or_insert(0);
+        *counter += 1;
+        *counter
+    }
+
+    /// Whether `block` is a theorem, without converting it
+    pub fn is_theorem(&self, block: &Block) -> bool {
+        match block {
+            Block::Div((_, classes, _), _) => to_theorem_type(&self.declared, classes).is_some(),
+            _ => false,
         }
     }
 
-    fn next_theorem(&mut self) -> u32 {
-        let current = self.theorem_counter;
-        self.theorem_counter += 1;
-        current
+    /// Returns the anchor associated to a theorem label
+    pub fn anchor(&self, label: &str) -> Option<&Anchor> {
+        self.theorems.get(label)
+    }
+
+    /// Takes the anchors of the theorems seen so far
+    pub fn take_anchors(&mut self) -> HashMap<String, Anchor> {
+        std::mem::take(&mut self.theorems)
+    }
+
+    /// Moves the hidden proofs of a theorem to the appendix.
+    ///
+    /// The theorem is given a label (if it has none) so that
+    /// the appendix can refer to it, and a restate macro
+    /// so that its statement can be repeated there.
+    /// Claim proofs keep their proofs in place.
+    pub fn send_proofs_to_appendix(&mut self, thm: &mut Theorem) {
+        if thm.kind == TheoremKind::ClaimProof {
+            return;
+        }
+        let (hidden, important): (Vec<Proof>, Vec<Proof>) = thm
+            .proofs
+            .drain(..)
+            .partition(|p| p.status == ProofStatus::Hidden);
+        thm.proofs = important;
+        if hidden.is_empty() {
+            return;
+        }
+
+        let label = match &thm.label {
+            Some(l) => l.clone(),
+            None => {
+                let l = self.fresh_label(&thm.kind);
+                self.theorems.insert(
+                    l.clone(),
+                    Anchor {
+                        label: l.clone(),
+                        title: thm.title.clone(),
+                        kind: AnchorKind::from(&thm.kind),
+                        number: thm.number.map(|n| n.to_string()),
+                    },
+                );
+                thm.label = Some(l.clone());
+                l
+            }
+        };
+        if thm.restatable.is_none() {
+            thm.restatable = Some(restate_macro(self.appendix.len() + 1));
+        }
+
+        let mut restated = thm.clone();
+        restated.proofs = vec![];
+        restated.appendix = vec![];
+        StripIdentifiers.visit_vec_block(&mut restated.statement);
+
+        for (i, proof) in hidden.into_iter().enumerate() {
+            let anchor = match i {
+                0 => format!("proof-of-{}", label),
+                _ => format!("proof-of-{}-{}", label, i + 1),
+            };
+            let number = format!("A.{}", self.appendix.len() + 1);
+            thm.appendix.push((anchor.clone(), number.clone()));
+            self.appendix.push(AppendixProof {
+                anchor,
+                number,
+                theorem: restated.clone(),
+                proof,
+            });
+        }
+    }
+
+    /// Takes the proofs that were sent to the appendix
+    /// so far, in order of appearance.
+    pub fn take_appendix(&mut self) -> Vec<AppendixProof> {
+        std::mem::take(&mut self.appendix)
+    }
+}
+
+/// A proof that was sent to the appendix,
+/// along with the theorem it proves, so that
+/// it can be restated.
+#[derive(Debug)]
+pub struct AppendixProof {
+    pub anchor: String,
+    pub number: String,
+    pub theorem: Theorem,
+    pub proof: Proof,
+}
+
+/// Name of the thm-restate macro generated for
+/// a theorem, LaTeX macros only contain letters
+/// 1 -> lipicsrestatea, 27 -> lipicsrestateaa
+fn restate_macro(number: usize) -> String {
+    let mut n = number;
+    let mut suffix = vec![];
+    while n > 0 {
+        n -= 1;
+        suffix.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    let suffix: String = suffix.into_iter().rev().collect();
+    format!("lipicsrestate{}", suffix)
+}
+
+/// Removes identifiers from restated content,
+/// so that anchors are not duplicated.
+struct StripIdentifiers;
+
+impl MutVisitor for StripIdentifiers {
+    fn visit_attr(&mut self, attr: &mut pandoc_ast::Attr) {
+        attr.0.clear();
+    }
+}
+
+/// The heading of the generated appendix section.
+fn appendix_heading() -> Vec<Inline> {
+    vec![Inline::Str("Omitted".to_string()), Inline::Space, Inline::Str("Proofs".to_string())]
+}
+
+/// Renders the proofs sent to the appendix in LaTeX.
+///
+/// \section{Omitted Proofs}
+/// \subsection{Proof of \cref{label}}\label{proof-of-label}
+/// \restatemacro*
+/// \begin{proofof}[label] body \end{proofof}
+pub fn appendix_to_latex(proofs: Vec<AppendixProof>) -> Vec<Block> {
+    let format = pandoc_ast::Format("latex".to_string());
+    let mut blocks = vec![Block::Header(
+        1,
+        ("omitted-proofs".to_string(), vec![], vec![]),
+        appendix_heading(),
+    )];
+    for entry in proofs {
+        let label = entry.theorem.label.unwrap_or_default();
+        blocks.push(Block::Header(
+            2,
+            (entry.anchor, vec![], vec![]),
+            vec![
+                Inline::Str("Proof".to_string()),
+                Inline::Space,
+                Inline::Str("of".to_string()),
+                Inline::Space,
+                Inline::RawInline(format.clone(), format!("\\cref{{{}}}", label)),
+            ],
+        ));
+        if let Some(restate) = entry.theorem.restatable {
+            blocks.push(Block::Plain(vec![Inline::RawInline(
+                format.clone(),
+                format!("\\{}*", restate),
+            )]));
+        }
+        blocks.push(Block::Plain(vec![Inline::RawInline(
+            format.clone(),
+            format!("\\begin{{proofof}}[{}]", label),
+        )]));
+        blocks.extend(entry.proof.body);
+        blocks.push(Block::Plain(vec![Inline::RawInline(
+            format.clone(),
+            "\\end{proofof}".to_string(),
+        )]));
+    }
+    blocks
+}
+
+/// Renders the proofs sent to the appendix for
+/// other output formats, numbering the sections
+/// by hand (Appendix A.1, A.2, ...).
+pub fn appendix_to_pandoc(proofs: Vec<AppendixProof>) -> Vec<Block> {
+    let unnumbered = vec!["unnumbered".to_string()];
+    let section_number = |n: &str| {
+        Inline::Span(
+            ("".to_string(), vec!["header-section-number".to_string()], vec![]),
+            vec![Inline::Str(n.to_string())],
+        )
+    };
+    let mut heading = vec![section_number("A"), Inline::Space];
+    heading.extend(appendix_heading());
+    let mut blocks = vec![Block::Header(
+        1,
+        ("omitted-proofs".to_string(), unnumbered.clone(), vec![]),
+        heading,
+    )];
+    for entry in proofs {
+        let mut theorem = entry.theorem;
+        theorem.label = None;
+        theorem.classes.insert("restated".to_string());
+        let mut heading = vec![
+            section_number(&entry.number),
+            Inline::Space,
+            Inline::Str("Proof".to_string()),
+            Inline::Space,
+            Inline::Str("of".to_string()),
+            Inline::Space,
+            Inline::Str(theorem.kind.name()),
+        ];
+        if let Some(number) = theorem.number {
+            heading.push(Inline::Space);
+            heading.push(Inline::Str(number.to_string()));
+        }
+        blocks.push(Block::Header(2, (entry.anchor, unnumbered.clone(), vec![]), heading));
+        blocks.extend(theorem_to_pandoc(theorem));
+        blocks.push(proof_to_pandoc(entry.proof));
+    }
+    blocks
+}
+
+type HeaderInfo = (i64, (pandoc_ast::Attr, Vec<Inline>));
+
+/// Separates headers (that delimit titles and proofs)
+/// from the other blocks of a theorem.
+#[allow(clippy::result_large_err)]
+fn header_or_block(block: Block) -> Result<HeaderInfo, Block> {
+    match block {
+        Block::Header(lvl, ident, inlines) => Ok((lvl, (ident, inlines))),
+        _ => Err(block),
     }
 }
 
 /// Block to theorem
 /// Converts a block to a theorem if possible
 /// otherwise returns None.
-fn block_to_theorem(ctx: &mut Context, block: Block) -> Option<Theorem> {
+///
+/// The label of the theorem is either the identifier of the div
+/// or the one of its title header, and the title can also
+/// be given as a `title` attribute. A `restate` (or `restatable`)
+/// attribute names the thm-restate macro of the theorem.
+pub fn block_to_theorem(ctx: &mut Context, block: Block) -> Option<Theorem> {
     match block {
         Block::Div((ident, classes, keyvals), blocks) => {
-            let theorem_type = to_theorem_type(&classes)?;
-            let thm_num = ctx.next_theorem();
+            let theorem_type = to_theorem_type(&ctx.declared, &classes)?;
+            let number = match theorem_type.is_numbered() {
+                true => Some(ctx.next_theorem(&theorem_type)),
+                false => None,
+            };
 
             let mut title: Option<Vec<Inline>> = None;
             let mut label: Option<String> = None;
-            let mut restatable: Option<String> = None;
-            let mut proofs: Vec<Proof> = vec![];
-            let mut statement: Vec<Block> = vec![];
+            let statement: Vec<Block>;
             let mut classes: HashSet<String> = classes.into_iter().collect();
             let mut keyvals: HashMap<String, String> = keyvals.into_iter().collect();
 
-            let decorated = blocks
-                .into_iter()
-                .map(|b| match b {
-                    Block::Header(lvl, ident, inlines) => Ok((lvl, (ident, inlines))),
-                    _ => Err(b),
-                })
-                .collect();
+            if !ident.is_empty() {
+                label = Some(ident);
+            }
+
+            let decorated = blocks.into_iter().map(header_or_block).collect();
 
             let (before, mut after) = polyreg::split_vec(decorated);
 
-            if !before.is_empty() {
+            if !before.is_empty() || after.is_empty() {
                 statement = before;
             } else {
                 let (block_title, block_statement) = after.remove(0);
@@ -255,6 +935,16 @@ fn block_to_theorem(ctx: &mut Context, block: Block) -> Option<Theorem> {
                 keyvals.extend(kvl);
             }
 
+            if title.is_none() {
+                title = keyvals
+                    .remove("title")
+                    .map(|t| vec![Inline::Str(t)]);
+            }
+
+            let restatable = keyvals
+                .remove("restate")
+                .or_else(|| keyvals.remove("restatable"));
+
             // now we collect the "proof blocks" that are in the after
             // array
             let proofs = after
@@ -263,7 +953,10 @@ fn block_to_theorem(ctx: &mut Context, block: Block) -> Option<Theorem> {
                     let (_, ((id, classes, keyvals), inlines)) = block_title;
                     let cls: HashSet<String> = classes.into_iter().collect();
                     let kvl: HashMap<String, String> = keyvals.into_iter().collect();
-                    let kind = match cls.contains("sketch") {
+                    // headers that only say "Proof" or "Proof Sketch"
+                    // are not titles, they only give the kind of the proof
+                    let heading = utils::stringify_inlines(&inlines).to_lowercase();
+                    let kind = match cls.contains("sketch") || heading == "proof sketch" || heading == "sketch" {
                         true => ProofKind::Sketch,
                         false => ProofKind::Proof,
                     };
@@ -271,10 +964,9 @@ fn block_to_theorem(ctx: &mut Context, block: Block) -> Option<Theorem> {
                         true => ProofStatus::Hidden,
                         false => ProofStatus::Important,
                     };
-                    let title = if !inlines.is_empty() {
-                        Some(inlines)
-                    } else {
-                        None
+                    let title = match heading.as_str() {
+                        "" | "proof" | "proof sketch" | "sketch" => None,
+                        _ => Some(inlines),
                     };
                     let label = if !id.is_empty() { Some(id) } else { None };
                     Proof {
@@ -289,62 +981,75 @@ fn block_to_theorem(ctx: &mut Context, block: Block) -> Option<Theorem> {
                 })
                 .collect();
 
+            if let Some(l) = &label {
+                ctx.theorems.insert(
+                    l.clone(),
+                    Anchor {
+                        label: l.clone(),
+                        title: title.clone(),
+                        kind: AnchorKind::from(&theorem_type),
+                        number: number.map(|n| n.to_string()),
+                    },
+                );
+            }
+
             Some(Theorem {
                 title,
                 kind: theorem_type,
+                number,
                 label,
                 restatable,
                 proofs,
                 statement,
                 classes,
                 keyvals,
+                appendix: vec![],
             })
         }
         _ => None,
     }
 }
 
-/// Ultimately, perform all the computations in this preprocessor, even for LaTeX output,
-/// so that we have a "one pass compilation" of the document for LaTeX, to speed up the
-/// view time. Note that for tikz pictures, this is irrelevant because we would have
-/// to parse them to get proper cross-references.
-///
-/// Better Figures.
-///
-/// - parse ![caption](url){.figure} to add it to the figures list
-/// - allow to use SVG or TIKZ code directly in the document
-///     -> for tikz, in latex, this is just plain latex code
-///     -> in other formats, collect all the latex codes, and compile
-///     a standalone latex document with the tikz code to get a pdf
-///     of the image, that is then rasterized into a low-res png.
-///
-/// Better Tables?
-///
-/// - TODO.
-///
-/// Better Algorithms.
-///
-/// - parse ```{=name .algorithm}``` to add it to the list of algorithms,
-///   and create a nicely formatted algorithm environment in LaTeX.
-///
-/// Better Macros.
-///
-/// -> for the moment, we will also use the "standalone" compilation of the math components
-/// to get png images of the macros. This is way simpler.
-///
-/// Checking.
-///
-/// - check that every theorem has a proof (or proof sketch): provide the list of theorems without proofs
-/// - check that knowledges are introduced before they are used.
-/// - check for consistency in the references.
-/// - provide an estimated number of pages.
-///
+// Ultimately, perform all the computations in this preprocessor, even for LaTeX output,
+// so that we have a "one pass compilation" of the document for LaTeX, to speed up the
+// view time. Note that for tikz pictures, this is irrelevant because we would have
+// to parse them to get proper cross-references.
+//
+// Better Figures.
+//
+// - parse ![caption](url){.figure} to add it to the figures list
+// - allow to use SVG or TIKZ code directly in the document
+//     -> for tikz, in latex, this is just plain latex code
+//     -> in other formats, collect all the latex codes, and compile
+//     a standalone latex document with the tikz code to get a pdf
+//     of the image, that is then rasterized into a low-res png.
+//
+// Better Tables?
+//
+// - TODO.
+//
+// Better Algorithms.
+//
+// - parse ```{=name .algorithm}``` to add it to the list of algorithms,
+//   and create a nicely formatted algorithm environment in LaTeX.
+//
+// Better Macros.
+//
+// -> for the moment, we will also use the "standalone" compilation of the math components
+// to get png images of the macros. This is way simpler.
+//
+// Checking.
+//
+// - check that every theorem has a proof (or proof sketch): provide the list of theorems without proofs
+// - check that knowledges are introduced before they are used.
+// - check for consistency in the references.
+// - provide an estimated number of pages.
 
 /// A proof kind in the lipics format.
 /// Proof is a direct proof, that should be shown
 /// Sketch is a proof sketch
-#[derive(Debug)]
-enum ProofKind {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofKind {
     Proof,
     Sketch,
 }
@@ -352,8 +1057,8 @@ enum ProofKind {
 /// Proof status in the lipics format.
 /// Either it is important (main body)
 /// or should be hidden (appendix / details)
-#[derive(Debug)]
-enum ProofStatus {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofStatus {
     Important,
     Hidden,
 }
@@ -361,13 +1066,295 @@ enum ProofStatus {
 /// A proof in the lipics format
 /// proof kind = "proof" | "proofof" | "sketch"
 /// proof body = block+
-#[derive(Debug)]
-struct Proof {
-    title: Option<Vec<Inline>>,
-    status: ProofStatus,
-    kind: ProofKind,
-    label: Option<String>,
-    body: Vec<Block>,
-    classes: HashSet<String>,
-    keyvals: HashMap<String, String>,
+#[derive(Debug, Clone)]
+pub struct Proof {
+    pub title: Option<Vec<Inline>>,
+    pub status: ProofStatus,
+    pub kind: ProofKind,
+    pub label: Option<String>,
+    pub body: Vec<Block>,
+    pub classes: HashSet<String>,
+    pub keyvals: HashMap<String, String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Vec<Inline> {
+        if s.is_empty() { vec![] } else { vec![Inline::Str(s.to_string())] }
+    }
+
+    fn header(id: &str, classes: &[&str], title: &str) -> Block {
+        Block::Header(3, (id.to_string(), classes.iter().map(|c| c.to_string()).collect(), vec![]), text(title))
+    }
+
+    fn div(id: &str, classes: &[&str], keyvals: &[(&str, &str)], blocks: Vec<Block>) -> Block {
+        let classes = classes.iter().map(|c| c.to_string()).collect();
+        let keyvals = keyvals.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Block::Div((id.to_string(), classes, keyvals), blocks)
+    }
+
+    /// The text of the blocks, one line per block,
+    /// with the content of the divs inlined
+    fn lines(blocks: &[Block]) -> Vec<String> {
+        blocks.iter().flat_map(|b| match b {
+            Block::Plain(i) | Block::Para(i) | Block::Header(_, _, i) => vec![crate::utils::stringify_inlines(i)],
+            Block::RawBlock(_, s) => vec![s.clone()],
+            Block::Div(_, blocks) => lines(blocks),
+            other => vec![format!("{:?}", other)],
+        }).collect()
+    }
+
+    #[test]
+    fn latex_with_title_and_proofs() {
+        let block = div("thm:a", &["lemma"], &[("restate", "lemA")], vec![
+            header("", &[], "Pumping"),
+            Block::Para(text("statement")),
+            header("", &["sketch"], ""),
+            Block::Para(text("idea")),
+            header("prf", &[], "Proof of the lemma"),
+            Block::Para(text("details")),
+        ]);
+        let thm = block_to_theorem(&mut Context::new(), block).unwrap();
+        assert_eq!(lines(&theorem_to_latex(thm)), vec![
+            "\\begin{lemma}[label={thm:a}, restate={lemA}, title={Pumping}]",
+            "statement",
+            "\\end{lemma}",
+            "\\begin{proof}[Proof Sketch]",
+            "idea",
+            "\\end{proof}",
+            "\\begin{proof}[Proof of the lemma]\\label{prf}",
+            "details",
+            "\\end{proof}",
+        ]);
+    }
+
+    #[test]
+    fn latex_without_title() {
+        let block = div("", &["theorem"], &[], vec![Block::Para(text("statement"))]);
+        let thm = block_to_theorem(&mut Context::new(), block).unwrap();
+        assert_eq!(lines(&theorem_to_latex(thm)), vec!["\\begin{theorem}", "statement", "\\end{theorem}"]);
+    }
+
+    #[test]
+    fn latex_claim_proofs() {
+        let block = div("clm:a", &["claimproof"], &[("restate", "clmA")], vec![
+            header("", &[], "Of the claim"),
+            Block::Para(text("details")),
+        ]);
+        let thm = block_to_theorem(&mut Context::new(), block).unwrap();
+        assert_eq!(lines(&theorem_to_latex(thm)), vec![
+            "\\begin{claimproof}[Of the claim]\\label{clm:a}",
+            "details",
+            "\\end{claimproof}",
+        ]);
+        let mut ctx = Context::new();
+        let block = div("", &["claimproof"], &[], vec![
+            Block::Para(text("details")),
+            header("", &["appendix"], ""),
+            Block::Para(text("more")),
+        ]);
+        let mut thm = block_to_theorem(&mut ctx, block).unwrap();
+        ctx.send_proofs_to_appendix(&mut thm);
+        assert!(ctx.take_appendix().is_empty());
+        assert_eq!(lines(&theorem_to_latex(thm)), vec![
+            "\\begin{claimproof}",
+            "details",
+            "\\end{claimproof}",
+            "\\begin{proof}",
+            "more",
+            "\\end{proof}",
+        ]);
+    }
+
+    #[test]
+    fn title_from_the_attributes() {
+        let mut ctx = Context::new();
+        let block = div("thm:b", &["theorem"], &[("title", "Main")], vec![Block::Para(text("statement"))]);
+        let thm = block_to_theorem(&mut ctx, block).unwrap();
+        assert_eq!(lines(&theorem_to_latex(thm))[0], "\\begin{theorem}[label={thm:b}, title={Main}]");
+        assert!(ctx.anchor("thm:b").is_some());
+    }
+
+    #[test]
+    fn other_divs() {
+        let block = div("", &["note"], &[], vec![Block::Para(text("statement"))]);
+        assert!(block_to_theorem(&mut Context::new(), block).is_none());
+    }
+
+    #[test]
+    fn html_numbering_and_proofs() {
+        let mut ctx = Context::new();
+        let first = div("", &["theorem"], &[], vec![Block::Para(text("statement"))]);
+        let first = block_to_theorem(&mut ctx, first).unwrap();
+        assert_eq!(lines(&theorem_to_html(first)), vec!["Theorem 1.", "statement"]);
+
+        let second = div("lem", &["lemma"], &[], vec![
+            header("", &[], "Pumping"),
+            Block::Para(text("statement")),
+            header("", &["sketch"], ""),
+            Block::Para(text("idea")),
+            header("prf", &[], "Details"),
+            Block::Para(text("details")),
+        ]);
+        let html = theorem_to_html(block_to_theorem(&mut ctx, second).unwrap());
+        match html.as_slice() {
+            [Block::Div((id, classes, _), _)] => {
+                assert_eq!(id, "lem");
+                assert_eq!(classes, &vec!["theorem".to_string(), "lemma".to_string()]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(lines(&html), vec![
+            "Lemma 2 (Pumping).",
+            "statement",
+            "<details class=\"proof sketch\">",
+            "<summary>",
+            "Proof sketch",
+            "</summary>",
+            "idea",
+            "</details>",
+            "<details class=\"proof\" id=\"prf\">",
+            "<summary>",
+            "Details",
+            "</summary>",
+            "details",
+            "</details>",
+        ]);
+    }
+
+    #[test]
+    fn pandoc_proofs() {
+        let block = div("", &["theorem"], &[], vec![
+            Block::Para(text("statement")),
+            header("", &[], ""),
+            Block::Para(text("details")),
+        ]);
+        let thm = block_to_theorem(&mut Context::new(), block).unwrap();
+        assert_eq!(lines(&theorem_to_pandoc(thm)), vec!["Theorem 1.", "statement", "Proof. details"]);
+    }
+
+    #[test]
+    fn appendix() {
+        let mut ctx = Context::new();
+        let block = div("", &["theorem"], &[], vec![
+            Block::Para(text("statement")),
+            header("", &["appendix"], ""),
+            Block::Para(text("details")),
+        ]);
+        let mut thm = block_to_theorem(&mut ctx, block).unwrap();
+        ctx.send_proofs_to_appendix(&mut thm);
+        assert_eq!(thm.label.as_deref(), Some("theorem-1"));
+        assert!(ctx.anchor("theorem-1").is_some());
+        assert_eq!(lines(&theorem_to_latex(thm.clone())), vec![
+            "\\begin{theorem}[label={theorem-1}, restate={lipicsrestatea}]",
+            "statement",
+            "\\hfill(see proof in \\cref{proof-of-theorem-1})",
+            "\\end{theorem}",
+        ]);
+        assert_eq!(lines(&theorem_to_html(thm)), vec!["Theorem 1.", "statement", "(see proof in Appendix A.1)"]);
+
+        let appendix = ctx.take_appendix();
+        assert_eq!(lines(&appendix_to_latex(appendix)), vec![
+            "Omitted Proofs",
+            "Proof of \\cref{theorem-1}",
+            "\\lipicsrestatea*",
+            "\\begin{proofof}[theorem-1]",
+            "details",
+            "\\end{proofof}",
+        ]);
+        assert!(ctx.take_appendix().is_empty());
+    }
+
+    #[test]
+    fn generated_labels() {
+        let mut ctx = Context::new();
+        ctx.reserve_ids(vec!["lemma-2".to_string()]);
+        let labels: Vec<String> = ["lemma", "lemma", "claim"].iter().map(|kind| {
+            let block = div("", &[kind], &[], vec![
+                Block::Para(text("statement")),
+                header("", &["appendix"], ""),
+                Block::Para(text("details")),
+            ]);
+            let mut thm = block_to_theorem(&mut ctx, block).unwrap();
+            ctx.send_proofs_to_appendix(&mut thm);
+            thm.label.unwrap()
+        }).collect();
+        assert_eq!(labels, vec!["lemma-1", "lemma-3", "claim-4"]);
+    }
+
+    #[test]
+    fn appendix_outside_of_latex() {
+        let mut ctx = Context::new();
+        let block = div("thm:a", &["lemma"], &[], vec![
+            Block::Para(text("statement")),
+            header("", &["appendix"], ""),
+            Block::Para(text("first")),
+            header("", &["appendix", "sketch"], ""),
+            Block::Para(text("second")),
+        ]);
+        let mut thm = block_to_theorem(&mut ctx, block).unwrap();
+        ctx.send_proofs_to_appendix(&mut thm);
+        assert_eq!(thm.appendix, vec![
+            ("proof-of-thm:a".to_string(), "A.1".to_string()),
+            ("proof-of-thm:a-2".to_string(), "A.2".to_string()),
+        ]);
+        assert_eq!(lines(&appendix_to_pandoc(ctx.take_appendix())), vec![
+            "A Omitted Proofs",
+            "A.1 Proof of Lemma 1",
+            "Lemma 1.",
+            "statement",
+            "Proof. first",
+            "A.2 Proof of Lemma 1",
+            "Lemma 1.",
+            "statement",
+            "Proof Sketch. second",
+        ]);
+    }
+
+    #[test]
+    fn restate_macros() {
+        assert_eq!(restate_macro(1), "lipicsrestatea");
+        assert_eq!(restate_macro(26), "lipicsrestatez");
+        assert_eq!(restate_macro(27), "lipicsrestateaa");
+    }
+
+    fn declaration(name: &str, title: &str, counter: Option<&str>) -> TheoremDeclaration {
+        TheoremDeclaration { name: name.to_string(), title: title.to_string(), counter: counter.map(String::from), style: None }
+    }
+
+    #[test]
+    fn transitive_counters() {
+        let declared = vec![
+            declaration("hypothesis", "Hypothesis", Some("lemma")),
+            declaration("conjecture", "Conjecture", Some("hypothesis")),
+            declaration("exercise", "Exercise & Solution", None),
+            declaration("question", "Question", Some("exercise")),
+            declaration("problem", "Problem", Some("puzzle")),
+            declaration("puzzle", "Puzzle", Some("problem")),
+        ];
+        let latex = match declarations_to_latex(&declared) {
+            Block::RawBlock(_, latex) => latex,
+            other => panic!("unexpected {:?}", other),
+        };
+        let newtheorems: Vec<&str> = latex.lines().filter(|l| l.starts_with("\\newtheorem")).collect();
+        assert_eq!(newtheorems, vec![
+            "\\newtheorem{hypothesis}[theorem]{Hypothesis}",
+            "\\newtheorem{conjecture}[theorem]{Conjecture}",
+            "\\newtheorem{exercise}{Exercise \\& Solution}",
+            "\\newtheorem{question}[exercise]{Question}",
+            "\\newtheorem{problem}[theorem]{Problem}",
+            "\\newtheorem{puzzle}[theorem]{Puzzle}",
+        ]);
+
+        let mut ctx = Context::with_declarations(declared);
+        let mut number = |class: &str| block_to_theorem(&mut ctx, div("", &[class], &[], vec![])).unwrap().number;
+        assert_eq!(number("lemma"), Some(1));
+        assert_eq!(number("conjecture"), Some(2));
+        assert_eq!(number("question"), Some(1));
+        assert_eq!(number("exercise"), Some(2));
+        assert_eq!(number("hypothesis"), Some(3));
+        assert_eq!(number("puzzle"), Some(4));
+    }
 }
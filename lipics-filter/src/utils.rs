@@ -1,4 +1,6 @@
-use pandoc_ast::{MetaValue, Inline};
+use nom::character::complete::char;
+use nom::IResult;
+use pandoc_ast::{Block, MetaValue, Inline};
 use std::collections::BTreeMap;
 
 /// Converts a MetaValue to a string if it is a MetaString
@@ -16,6 +18,68 @@ pub fn meta_to_string(meta : &MetaValue) -> Option<String> {
     }
 }
 
+/// Converts a yaml (or json) value to a MetaValue,
+/// as if it were written in the metadata block
+pub fn yaml_to_meta(value : &serde_yaml::Value) -> Option<MetaValue> {
+    use serde_yaml::Value;
+    match value {
+        Value::Null => None,
+        Value::Bool(b) => Some(MetaValue::MetaBool(*b)),
+        Value::Number(n) => Some(MetaValue::MetaString(n.to_string())),
+        Value::String(s) => Some(MetaValue::MetaString(s.clone())),
+        Value::Sequence(l) => Some(MetaValue::MetaList(l.iter().filter_map(yaml_to_meta).collect())),
+        Value::Mapping(m) => Some(MetaValue::MetaMap(m.iter().filter_map(|(k,v)| {
+            let key = match k {
+                Value::String(s) => s.clone(),
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => b.to_string(),
+                _ => return None
+            };
+            Some((key, Box::new(yaml_to_meta(v)?)))
+        }).collect())),
+        Value::Tagged(t) => yaml_to_meta(&t.value),
+    }
+}
+
+/// Escapes the special characters of LaTeX in a text
+pub fn escape_latex(s : &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            '#' | '$' | '%' | '&' | '_' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escapes an entry of `\index`: the special characters
+/// of LaTeX and the quote `"` of makeindex are escaped,
+/// but `!`, `@` and `|` are kept to structure the entry
+/// (subentries, sort keys and page formats)
+pub fn escape_index(s : &str) -> String {
+    escape_latex(s).replace('"', "\"\"")
+}
+
+/// Converts a MetaValue to a boolean if it is a MetaBool
+/// or one of the strings true / false
+pub fn meta_to_bool(meta : &MetaValue) -> Option<bool> {
+    match meta {
+        MetaValue::MetaBool(b) => Some(*b),
+        _ => match meta_to_string(meta)?.as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None
+        }
+    }
+}
+
 /// Converts a MetaValue to a vector of inlines if it is a MetaString or MetaInlines
 pub fn meta_to_inline(meta : &MetaValue) -> Option<Vec<Inline>> {
     match meta {
@@ -29,14 +93,102 @@ pub fn meta_to_inline(meta : &MetaValue) -> Option<Vec<Inline>> {
     }
 }
 
+/// Converts a MetaValue to a vector of blocks if it contains text
+pub fn meta_to_blocks(meta : &MetaValue) -> Option<Vec<Block>> {
+    match meta {
+        MetaValue::MetaBlocks(b) => Some(b.clone()),
+        _ => meta_to_inline(meta).map(|i| vec![Block::Plain(i)]),
+    }
+}
+
+/// Appends a value to a list in the metadata,
+/// turning a single value into a list if needed
+/// (e.g. `header-includes`).
+pub fn meta_append(meta : &mut BTreeMap<String, MetaValue>, key : &str, value : MetaValue) {
+    match meta.remove(key) {
+        Some(MetaValue::MetaList(mut l)) => {
+            l.push(value);
+            meta.insert(key.to_string(), MetaValue::MetaList(l));
+        }
+        Some(other) => {
+            meta.insert(key.to_string(), MetaValue::MetaList(vec![other, value]));
+        }
+        None => {
+            meta.insert(key.to_string(), MetaValue::MetaList(vec![value]));
+        }
+    }
+}
+
+/// Inserts a value at a dotted path of maps,
+/// creating (or replacing) the intermediate maps
+/// meta_deep_insert(meta, "lipics.report", v)
+pub fn meta_deep_insert(meta : &mut BTreeMap<String, MetaValue>, path : &str, value : MetaValue) {
+    match path.split_once('.') {
+        None => {
+            meta.insert(path.to_string(), value);
+        }
+        Some((first, rest)) => {
+            let entry = meta.entry(first.to_string()).or_insert_with(|| MetaValue::MetaMap(BTreeMap::new()));
+            if !matches!(entry, MetaValue::MetaMap(_)) {
+                *entry = MetaValue::MetaMap(BTreeMap::new());
+            }
+            if let MetaValue::MetaMap(m) = entry {
+                let mut inner : BTreeMap<String, MetaValue> = std::mem::take(m).into_iter().map(|(k,v)| (k,*v)).collect();
+                meta_deep_insert(&mut inner, rest, value);
+                *m = inner.into_iter().map(|(k,v)| (k, Box::new(v))).collect();
+            }
+        }
+    }
+}
+
+/// The Levenshtein distance between two strings
+pub fn edit_distance(a : &str, b : &str) -> usize {
+    let b : Vec<char> = b.chars().collect();
+    let mut row : Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(row[j + 1])
+            };
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+/// Parses a value between balanced braces
+/// {Computer {P}rogramming} -> Computer {P}rogramming
+pub fn braced(input: &str) -> IResult<&str, &str> {
+    let (rest, _) = char('{')(input)?;
+    let mut depth = 1;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok((&rest[i + 1..], &rest[..i]));
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Char)))
+}
+
 pub fn stringify_inlines(i : &[Inline]) -> String {
-    i.iter().map(|x| stringify(x)).collect::<Vec<String>>().join("")
+    i.iter().map(stringify).collect::<Vec<String>>().join("")
 }
 
 pub fn stringify(i : &Inline) -> String {
     match i {
         Inline::Str(s) => s.clone(),
-        Inline::Note(i) => "".into(),
+        Inline::Note(_) => "".into(),
         Inline::Emph(i) => stringify_inlines(i),
         Inline::Strong(i) => stringify_inlines(i),
         Inline::Underline(i) => stringify_inlines(i),
@@ -52,7 +204,7 @@ pub fn stringify(i : &Inline) -> String {
         Inline::LineBreak => "\n".into(),
         Inline::Math(_,s) => s.clone(),
         Inline::RawInline(_,s) => s.clone(),
-        Inline::Link(_,i,t) => stringify_inlines(i),
+        Inline::Link(_,i,_) => stringify_inlines(i),
         Inline::Image(_,_,_) => "".into(),
         Inline::Span(_,i) => stringify_inlines(i),
     }
@@ -111,7 +263,7 @@ fn string_to_selector(s : &str) -> Vec<MetaSelect> {
 pub fn meta_deep_get(meta : &BTreeMap<String, MetaValue>, path : &str) -> Option<MetaValue> {
     let selector     = string_to_selector(path);
     let (first,rest) = selector.split_at(1);
-    if let Some(MetaSelect::Field(f)) = first.iter().nth(0) {
+    if let Some(MetaSelect::Field(f)) = first.first() {
         if let Some(m) = meta.get(f) {
             meta_deep_get_internal(m, rest)
         } else {